@@ -0,0 +1,198 @@
+//! Following `!context,event` aliases to the inputs they stand for.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use crate::format::{Binding, ControlMapFile, Device, Input};
+use crate::input_codes::InputNames;
+
+/// The event an alias points to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AliasTarget {
+    pub context: usize,
+    pub event: String,
+}
+
+impl Display for AliasTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "!{},{}", self.context, self.event)
+    }
+}
+
+/// A concrete input reached while resolving a binding.
+#[derive(Debug)]
+pub struct ResolvedInput {
+    /// The aliases that were followed to reach this input, outermost first.
+    /// Empty if the input was written directly in the binding.
+    pub via: Vec<AliasTarget>,
+
+    /// The keys making up the input. Empty if the aliased event isn't bound
+    /// to anything on this device.
+    pub codes: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub struct AliasError {
+    /// The aliases that were followed, ending with the one that failed.
+    pub via: Vec<AliasTarget>,
+    pub kind: AliasErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasErrorKind {
+    UnknownContext,
+    UnknownEvent,
+    Cycle,
+}
+
+impl Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = self.via.last().unwrap();
+
+        match self.kind {
+            AliasErrorKind::UnknownContext => {
+                write!(f, "alias {} points to a context that doesn't exist", target)
+            }
+            AliasErrorKind::UnknownEvent => {
+                write!(f, "alias {} points to an event that doesn't exist", target)
+            }
+            AliasErrorKind::Cycle => {
+                write!(f, "alias {} refers back to itself", target)
+            }
+        }
+    }
+}
+
+impl Error for AliasError {}
+
+impl ControlMapFile {
+    /// Resolves every alternative of a binding on the given device, following
+    /// aliases into other sections. Results are in binding order, with
+    /// dangling and cyclic aliases reported in place.
+    pub fn trace_binding(
+        &self,
+        binding: &Binding,
+        device: Device,
+    ) -> Vec<Result<ResolvedInput, AliasError>> {
+        let mut resolved = Vec::new();
+        self.trace_into(binding, device, &mut Vec::new(), &mut resolved);
+        resolved
+    }
+
+    /// Expands a binding into the concrete inputs it stands for on the given
+    /// device. Aliases that point nowhere or loop back on themselves
+    /// contribute no inputs.
+    pub fn resolve_binding(&self, binding: &Binding, device: Device) -> Vec<Vec<u32>> {
+        self.trace_binding(binding, device)
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|resolved| resolved.codes)
+            .filter(|codes| !codes.is_empty())
+            .collect()
+    }
+
+    /// Describes a binding using key names, spelling out the chain of
+    /// aliases behind each input, like `!0,Activate → E`.
+    pub fn explain_binding(&self, binding: &Binding, device: Device, names: InputNames) -> String {
+        let traced = self.trace_binding(binding, device);
+        if traced.is_empty() {
+            return "<nothing>".to_owned();
+        }
+
+        let alternatives: Vec<_> = traced
+            .into_iter()
+            .map(|resolved| {
+                let (via, end) = match resolved {
+                    Ok(ResolvedInput { via, codes }) if codes.is_empty() => {
+                        (via, "<nothing>".to_owned())
+                    }
+                    Ok(ResolvedInput { via, codes }) => {
+                        let chord = Binding {
+                            inputs: vec![Input::Inputs(codes)],
+                        };
+                        (via, device.describe(&chord, names))
+                    }
+                    Err(err) => {
+                        let end = match err.kind {
+                            AliasErrorKind::UnknownContext => "<unknown context>",
+                            AliasErrorKind::UnknownEvent => "<unknown event>",
+                            AliasErrorKind::Cycle => "<alias cycle>",
+                        };
+                        (err.via, end.to_owned())
+                    }
+                };
+
+                via.iter()
+                    .map(ToString::to_string)
+                    .chain(std::iter::once(end))
+                    .collect::<Vec<_>>()
+                    .join(" → ")
+            })
+            .collect();
+
+        alternatives.join(" OR ")
+    }
+
+    fn trace_into(
+        &self,
+        binding: &Binding,
+        device: Device,
+        via: &mut Vec<AliasTarget>,
+        resolved: &mut Vec<Result<ResolvedInput, AliasError>>,
+    ) {
+        for input in &binding.inputs {
+            match input {
+                Input::Inputs(codes) => resolved.push(Ok(ResolvedInput {
+                    via: via.clone(),
+                    codes: codes.clone(),
+                })),
+
+                Input::Alias { context, event } => {
+                    let target = AliasTarget {
+                        context: *context,
+                        event: event.clone(),
+                    };
+                    let is_cycle = via.contains(&target);
+                    via.push(target);
+
+                    let error = |via: &Vec<AliasTarget>, kind| {
+                        Err(AliasError {
+                            via: via.clone(),
+                            kind,
+                        })
+                    };
+
+                    if is_cycle {
+                        resolved.push(error(via, AliasErrorKind::Cycle));
+                    } else if let Some(section) = self.sections.get(*context) {
+                        let mut targets = section
+                            .entries()
+                            .filter(|entry| &entry.event == event)
+                            .peekable();
+
+                        if targets.peek().is_none() {
+                            resolved.push(error(via, AliasErrorKind::UnknownEvent));
+                        }
+
+                        for target in targets {
+                            let target_binding = target.binding(device);
+
+                            if target_binding.inputs.is_empty() {
+                                resolved.push(Ok(ResolvedInput {
+                                    via: via.clone(),
+                                    codes: Vec::new(),
+                                }));
+                            }
+
+                            self.trace_into(target_binding, device, via, resolved);
+                        }
+                    } else {
+                        resolved.push(error(via, AliasErrorKind::UnknownContext));
+                    }
+
+                    via.pop();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Printable tables of what every event is bound to, for publishing as a
+//! controls reference.
+
+use std::fmt::Write;
+
+use crate::format::{Binding, ControlMapEntry, ControlMapFile, Device, Input};
+use crate::input_codes::InputNames;
+use crate::input_context::{section_name, InputContext};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheatSheetOptions {
+    /// Leave out events that aren't bound to anything on any device.
+    pub hide_unbound: bool,
+
+    /// Leave out the input contexts only used by the game's debug tools.
+    pub hide_debug: bool,
+
+    /// How to name keys and gamepad buttons.
+    pub names: InputNames,
+}
+
+/// One table of a cheat sheet: an input context and the bindings of each of
+/// its events, with aliases already resolved.
+struct Table {
+    title: String,
+    rows: Vec<[String; 4]>,
+}
+
+const HEADINGS: [&str; 4] = ["Event", "Keyboard", "Mouse", "Gamepad"];
+
+impl ControlMapFile {
+    /// Lays out the bindings of every event as one Markdown table per input
+    /// context.
+    pub fn cheat_sheet_markdown(&self, options: CheatSheetOptions) -> String {
+        let mut output = String::from("# Controls\n");
+
+        for table in self.cheat_sheet_tables(options) {
+            write!(output, "\n## {}\n\n", table.title).unwrap();
+            writeln!(output, "| {} |", HEADINGS.join(" | ")).unwrap();
+            writeln!(output, "|{}", " --- |".repeat(HEADINGS.len())).unwrap();
+
+            for row in table.rows {
+                let cells: Vec<String> = row.iter().map(|cell| escape_markdown(cell)).collect();
+                writeln!(output, "| {} |", cells.join(" | ")).unwrap();
+            }
+        }
+
+        output
+    }
+
+    /// Lays out the bindings of every event as a standalone HTML page, with
+    /// one table per input context.
+    pub fn cheat_sheet_html(&self, options: CheatSheetOptions) -> String {
+        let mut output = String::from(
+            "<!DOCTYPE html>\n\
+             <html>\n\
+             <head>\n\
+             <meta charset=\"utf-8\">\n\
+             <title>Controls</title>\n\
+             <style>\n\
+             table { border-collapse: collapse; margin-bottom: 2em; }\n\
+             th, td { border: 1px solid #999; padding: 0.25em 0.75em; text-align: left; }\n\
+             </style>\n\
+             </head>\n\
+             <body>\n\
+             <h1>Controls</h1>\n",
+        );
+
+        for table in self.cheat_sheet_tables(options) {
+            writeln!(output, "<h2>{}</h2>", escape_html(&table.title)).unwrap();
+            output.push_str("<table>\n<tr>");
+            for heading in HEADINGS {
+                write!(output, "<th>{}</th>", heading).unwrap();
+            }
+            output.push_str("</tr>\n");
+
+            for row in table.rows {
+                output.push_str("<tr>");
+                for cell in &row {
+                    write!(output, "<td>{}</td>", escape_html(cell)).unwrap();
+                }
+                output.push_str("</tr>\n");
+            }
+
+            output.push_str("</table>\n");
+        }
+
+        output.push_str("</body>\n</html>\n");
+        output
+    }
+
+    fn cheat_sheet_tables(&self, options: CheatSheetOptions) -> Vec<Table> {
+        let mut tables = Vec::new();
+
+        for (section_id, section) in self.sections.iter().enumerate() {
+            let context = InputContext::from_u32(section_id as u32);
+            if options.hide_debug && context.is_some_and(|context| context.is_debug()) {
+                continue;
+            }
+
+            // Events with several entries get a single row listing all of
+            // their inputs.
+            let mut rows: Vec<[String; 4]> = Vec::new();
+            for entry in section.entries() {
+                let [keyboard, mouse, gamepad] = Device::all()
+                    .map(|device| self.describe_resolved(entry, device, options.names));
+                let row = [entry.event.clone(), keyboard, mouse, gamepad];
+
+                match rows.iter_mut().find(|existing| existing[0] == entry.event) {
+                    Some(existing) => {
+                        for (cell, new) in existing.iter_mut().zip(row).skip(1) {
+                            if cell.is_empty() {
+                                *cell = new;
+                            } else if !new.is_empty() && *cell != new {
+                                *cell = format!("{} or {}", cell, new);
+                            }
+                        }
+                    }
+                    None => rows.push(row),
+                }
+            }
+
+            if options.hide_unbound {
+                rows.retain(|row| row[1..].iter().any(|cell| !cell.is_empty()));
+            }
+
+            if !rows.is_empty() {
+                tables.push(Table {
+                    title: section_name(section_id),
+                    rows,
+                });
+            }
+        }
+
+        tables
+    }
+
+    /// Describes the inputs an entry is bound to on a device once aliases are
+    /// followed, or nothing if it isn't bound.
+    fn describe_resolved(
+        &self,
+        entry: &ControlMapEntry,
+        device: Device,
+        names: InputNames,
+    ) -> String {
+        let mut alternatives: Vec<String> = Vec::new();
+
+        for chord in self.resolve_binding(entry.binding(device), device) {
+            let described = device.describe(
+                &Binding {
+                    inputs: vec![Input::Inputs(chord)],
+                },
+                names,
+            );
+
+            if !alternatives.contains(&described) {
+                alternatives.push(described);
+            }
+        }
+
+        alternatives.join(" or ")
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_CONTROLMAP;
+
+    #[test]
+    fn resolves_aliases() {
+        let map: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+
+        let markdown = map.cheat_sheet_markdown(CheatSheetOptions::default());
+        let menu = &markdown[markdown.find("## Menu\n").unwrap()..];
+
+        assert!(menu.contains("\n| Accept | E |  | A |\n"));
+        assert!(menu.contains("\n| Cancel | Tab or Escape |  | B |\n"));
+    }
+}
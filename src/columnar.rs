@@ -15,6 +15,10 @@ impl ColumnPrinter {
         Self { rows: Vec::new() }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
     pub fn row(&mut self) {
         self.rows.push(Vec::new());
     }
@@ -63,3 +67,57 @@ impl ColumnPrinter {
         Ok(())
     }
 }
+
+/// Finds the display column where each tab-separated value in a line starts,
+/// expanding tabs to 4-space tab stops.
+pub fn column_starts(line: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut column = 0;
+    let mut in_value = false;
+
+    for c in line.chars() {
+        if c == '\t' {
+            column = next_tab_stop(column);
+            in_value = false;
+        } else {
+            if !in_value {
+                starts.push(column);
+                in_value = true;
+            }
+
+            column += 1;
+        }
+    }
+
+    starts
+}
+
+/// Writes values separated by tabs so that each one starts at or after the
+/// matching column in `starts`, like a row printed by `ColumnPrinter`.
+pub fn align_to(values: &[String], starts: &[usize]) -> String {
+    let mut output = String::new();
+    let mut column = 0;
+
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            // Always separate values with at least one tab.
+            column = next_tab_stop(column);
+            output.push('\t');
+
+            let target = starts.get(i).copied().unwrap_or(0);
+            while column < target {
+                column = next_tab_stop(column);
+                output.push('\t');
+            }
+        }
+
+        output.push_str(value);
+        column += value.chars().count();
+    }
+
+    output
+}
+
+fn next_tab_stop(column: usize) -> usize {
+    column + TAB_WIDTH - (column % TAB_WIDTH)
+}
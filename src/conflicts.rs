@@ -0,0 +1,87 @@
+//! Detection of inputs that trigger more than one event in the same section.
+
+use std::collections::BTreeMap;
+
+use crate::format::{ControlMapFile, Device};
+
+/// An input that is bound to several different events in one section.
+#[derive(Debug)]
+pub struct Conflict {
+    pub section: usize,
+    pub device: Device,
+
+    /// The keys that make up the input. More than one key means a chord.
+    pub chord: Vec<u32>,
+
+    /// Every event bound to the input, along with the line it came from.
+    pub events: Vec<(String, Option<usize>)>,
+}
+
+impl ControlMapFile {
+    /// Finds every input that is bound to more than one event within a
+    /// section. Aliases are resolved first, so an entry aliasing another
+    /// event conflicts with anything else that uses that event's keys.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+
+        for (section_id, section) in self.sections.iter().enumerate() {
+            for device in Device::all() {
+                let mut users: BTreeMap<Vec<u32>, Vec<(String, Option<usize>)>> = BTreeMap::new();
+
+                for entry in section.entries() {
+                    for mut chord in self.resolve_binding(entry.binding(device), device) {
+                        chord.sort_unstable();
+
+                        let events = users.entry(chord).or_default();
+                        if !events.iter().any(|(event, _)| event == &entry.event) {
+                            events.push((entry.event.clone(), entry.line));
+                        }
+                    }
+                }
+
+                for (chord, events) in users {
+                    if events.len() > 1 {
+                        conflicts.push(Conflict {
+                            section: section_id,
+                            device,
+                            chord,
+                            events,
+                        });
+                    }
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_codes::{Gamepad, InputCode};
+    use crate::input_context::InputContext;
+    use crate::DEFAULT_CONTROLMAP;
+
+    #[test]
+    fn finds_book_pages_sharing_the_right_stick() {
+        let map: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+
+        let conflicts = map.conflicts();
+        let book: Vec<_> = conflicts
+            .iter()
+            .filter(|conflict| conflict.section == InputContext::Book as usize)
+            .collect();
+
+        assert_eq!(book.len(), 1);
+        assert_eq!(book[0].device, Device::Gamepad);
+        assert_eq!(book[0].chord, [Gamepad::RightStick.to_u32()]);
+        assert_eq!(
+            book[0].events,
+            [
+                ("PrevPage".to_owned(), Some(131)),
+                ("NextPage".to_owned(), Some(132))
+            ]
+        );
+    }
+}
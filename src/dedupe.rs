@@ -0,0 +1,245 @@
+//! Finding and cleaning up events that appear more than once in a section.
+
+use std::collections::HashMap;
+use std::mem::take;
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::format::{ControlMapEntry, ControlMapFile, ControlMapLine, Device};
+use crate::input_context::InputContext;
+
+/// Events that the vanilla controlmap binds on more than one line on purpose,
+/// usually once to keys and once to aliases of the movement controls.
+const INTENTIONAL_DUPLICATES: &[(InputContext, &str)] = &[
+    (InputContext::Book, "PrevPage"),
+    (InputContext::Book, "NextPage"),
+    (InputContext::Lockpicking, "RotateLock"),
+];
+
+/// Tells whether an event is known to be defined more than once on purpose.
+pub fn is_intentional_duplicate(section: usize, event: &str) -> bool {
+    INTENTIONAL_DUPLICATES
+        .iter()
+        .any(|&(context, name)| context as usize == section && name == event)
+}
+
+/// What to do with events that appear more than once in a section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupePolicy {
+    /// Keep only the last entry for every event, since entries added later,
+    /// usually by mods, are meant to override earlier ones.
+    Remove,
+
+    /// Combine every entry for an event into the first one, keeping all of
+    /// their bindings.
+    Merge,
+
+    /// Keep the duplicates vanilla relies on, and remove the rest like
+    /// `Remove`.
+    KeepKnown,
+
+    /// Leave every entry alone.
+    Report,
+}
+
+impl FromStr for DedupePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remove" => Ok(DedupePolicy::Remove),
+            "merge" => Ok(DedupePolicy::Merge),
+            "keep-known" => Ok(DedupePolicy::KeepKnown),
+            "report" => Ok(DedupePolicy::Report),
+            _ => bail!(
+                "Unknown dedupe policy {}, expected remove, merge, keep-known, or report",
+                s
+            ),
+        }
+    }
+}
+
+/// An event with more than one entry in a section.
+#[derive(Debug)]
+pub struct Duplicate {
+    pub section: usize,
+    pub event: String,
+
+    /// The line each entry came from, in order.
+    pub lines: Vec<Option<usize>>,
+
+    /// Whether vanilla defines this event more than once too.
+    pub intentional: bool,
+}
+
+impl ControlMapFile {
+    /// Finds every event that has more than one entry in its section.
+    pub fn duplicates(&self) -> Vec<Duplicate> {
+        let mut duplicates = Vec::new();
+
+        for (section_id, section) in self.sections.iter().enumerate() {
+            let mut order = Vec::new();
+            let mut lines: HashMap<&str, Vec<Option<usize>>> = HashMap::new();
+
+            for entry in section.entries() {
+                lines
+                    .entry(&entry.event)
+                    .or_insert_with(|| {
+                        order.push(entry.event.as_str());
+                        Vec::new()
+                    })
+                    .push(entry.line);
+            }
+
+            for event in order {
+                let lines = lines.remove(event).unwrap();
+
+                if lines.len() > 1 {
+                    duplicates.push(Duplicate {
+                        section: section_id,
+                        event: event.to_owned(),
+                        lines,
+                        intentional: is_intentional_duplicate(section_id, event),
+                    });
+                }
+            }
+        }
+
+        duplicates
+    }
+
+    /// Deals with duplicate entries according to `policy`, returning every
+    /// duplicate that was found beforehand.
+    pub fn dedupe(&mut self, policy: DedupePolicy) -> Vec<Duplicate> {
+        let duplicates = self.duplicates();
+
+        for (section_id, section) in self.sections.iter_mut().enumerate() {
+            let mut first_entries: HashMap<String, usize> = HashMap::new();
+            let mut remaining: HashMap<String, usize> = HashMap::new();
+            for entry in section.entries() {
+                *remaining.entry(entry.event.clone()).or_insert(0) += 1;
+            }
+
+            for line in take(&mut section.body) {
+                let entry = match line {
+                    ControlMapLine::Entry(entry) => entry,
+                    other => {
+                        section.body.push(other);
+                        continue;
+                    }
+                };
+
+                let count = remaining.get_mut(&entry.event).unwrap();
+                *count -= 1;
+                let is_last = *count == 0;
+
+                let keep = match policy {
+                    DedupePolicy::Remove => is_last,
+                    DedupePolicy::KeepKnown => {
+                        is_last || is_intentional_duplicate(section_id, &entry.event)
+                    }
+                    DedupePolicy::Report => true,
+                    DedupePolicy::Merge => match first_entries.get(&entry.event) {
+                        Some(&first) => {
+                            if let ControlMapLine::Entry(first) = &mut section.body[first] {
+                                merge_entries(first, &entry);
+                            }
+
+                            false
+                        }
+                        None => {
+                            first_entries.insert(entry.event.clone(), section.body.len());
+                            true
+                        }
+                    },
+                };
+
+                if keep {
+                    section.body.push(ControlMapLine::Entry(entry));
+                }
+            }
+        }
+
+        duplicates
+    }
+}
+
+/// Adds the bindings and flags of `other` to `entry`. Alternatives `entry`
+/// already has aren't added again.
+fn merge_entries(entry: &mut ControlMapEntry, other: &ControlMapEntry) {
+    for device in Device::all() {
+        let binding = entry.binding_mut(device);
+
+        for input in &other.binding(device).inputs {
+            if !binding
+                .inputs
+                .iter()
+                .any(|existing| existing.matches(input))
+            {
+                binding.inputs.push(input.clone());
+            }
+        }
+
+        *entry.mappable_mut(device) |= other.mappable(device);
+    }
+
+    entry.event_flag = match (entry.event_flag, other.event_flag) {
+        (Some(flag), Some(other_flag)) => Some(flag | other_flag),
+        (flag, other_flag) => flag.or(other_flag),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Binding;
+
+    const SECTION: &str = "\
+Jump\t0x39\t0xff\t0x8000\t1\t1\t1\t0x801
+Sprint\t0x38\t0xff\t0x0100\t1\t1\t1\t0x801
+Jump\t0x2d\t0xff\t0x4000\t1\t1\t1\t0x801
+";
+
+    fn keyboard_bindings(map: &ControlMapFile) -> Vec<(String, Binding)> {
+        map.sections[0]
+            .entries()
+            .map(|entry| (entry.event.clone(), entry.keyboard.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn remove_keeps_the_last_entry() {
+        let mut map: ControlMapFile = SECTION.parse().unwrap();
+        let expected: Vec<_> = keyboard_bindings(&map).into_iter().skip(1).collect();
+
+        let duplicates = map.dedupe(DedupePolicy::Remove);
+
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].lines, vec![Some(1), Some(3)]);
+        assert_eq!(keyboard_bindings(&map), expected);
+    }
+
+    #[test]
+    fn merge_combines_into_the_first_entry() {
+        let mut map: ControlMapFile = SECTION.parse().unwrap();
+
+        map.dedupe(DedupePolicy::Merge);
+
+        let events: Vec<_> = map.sections[0]
+            .entries()
+            .map(|entry| &entry.event)
+            .collect();
+        assert_eq!(events, ["Jump", "Sprint"]);
+        assert_eq!(
+            map.sections[0]
+                .entries()
+                .next()
+                .unwrap()
+                .keyboard
+                .inputs
+                .len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Semantic comparison of two controlmap files.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use crate::event_flags::EventFlags;
+use crate::format::{Binding, ControlMapEntry, ControlMapFile, ControlMapSection, Device};
+use crate::input_codes::InputNames;
+
+/// One difference between two controlmap files.
+#[derive(Debug)]
+pub struct Change {
+    pub section: usize,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug)]
+pub enum ChangeKind {
+    SectionAdded,
+    SectionRemoved,
+    EventAdded {
+        event: String,
+    },
+    EventRemoved {
+        event: String,
+    },
+    BindingChanged {
+        event: String,
+
+        /// Which entry for the event changed, counting from zero, when the
+        /// event appears more than once in the section.
+        occurrence: usize,
+
+        device: Device,
+        old: Binding,
+        new: Binding,
+    },
+    MappableChanged {
+        event: String,
+
+        /// Which entry for the event changed, counting from zero, when the
+        /// event appears more than once in the section.
+        occurrence: usize,
+
+        device: Device,
+        old: bool,
+        new: bool,
+    },
+    EventFlagChanged {
+        event: String,
+
+        /// Which entry for the event changed, counting from zero, when the
+        /// event appears more than once in the section.
+        occurrence: usize,
+
+        old: Option<EventFlags>,
+        new: Option<EventFlags>,
+    },
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeKind::SectionAdded => write!(f, "section added"),
+            ChangeKind::SectionRemoved => write!(f, "section removed"),
+            ChangeKind::EventAdded { event } => write!(f, "+ {}", event),
+            ChangeKind::EventRemoved { event } => write!(f, "- {}", event),
+            ChangeKind::BindingChanged {
+                event,
+                occurrence,
+                device,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {}: {} changed from {} to {}",
+                EventDisplay(event, *occurrence),
+                device,
+                device.describe(old, InputNames::default()),
+                device.describe(new, InputNames::default())
+            ),
+            ChangeKind::MappableChanged {
+                event,
+                occurrence,
+                device,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {}: {} mappable changed from {} to {}",
+                EventDisplay(event, *occurrence),
+                device,
+                *old as u8,
+                *new as u8
+            ),
+            ChangeKind::EventFlagChanged {
+                event,
+                occurrence,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ {}: event flag changed from {} to {}",
+                EventDisplay(event, *occurrence),
+                FlagDisplay(*old),
+                FlagDisplay(*new)
+            ),
+        }
+    }
+}
+
+/// Writes an event name, followed by `#2`, `#3`, and so on for later entries
+/// of an event that appears more than once in a section. Patches refer to
+/// those entries the same way.
+pub struct EventDisplay<'a>(pub &'a str, pub usize);
+
+impl Display for EventDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            0 => write!(f, "{}", self.0),
+            occurrence => write!(f, "{}#{}", self.0, occurrence + 1),
+        }
+    }
+}
+
+struct FlagDisplay(Option<EventFlags>);
+
+impl Display for FlagDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(flag) => write!(f, "{}", flag),
+            None => write!(f, "<none>"),
+        }
+    }
+}
+
+impl ControlMapFile {
+    /// Lists the differences between this file and `other`. Entries are
+    /// paired up by section and event name. When an event appears more than
+    /// once in a section, occurrences are paired up in order.
+    pub fn diff(&self, other: &ControlMapFile) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let num_sections = self.sections.len().max(other.sections.len());
+
+        for section in 0..num_sections {
+            match (self.sections.get(section), other.sections.get(section)) {
+                (Some(old), Some(new)) => diff_section(section, old, new, &mut changes),
+                (Some(_), None) => changes.push(Change {
+                    section,
+                    kind: ChangeKind::SectionRemoved,
+                }),
+                (None, Some(_)) => changes.push(Change {
+                    section,
+                    kind: ChangeKind::SectionAdded,
+                }),
+                (None, None) => unreachable!(),
+            }
+        }
+
+        changes
+    }
+}
+
+fn diff_section(
+    section: usize,
+    old: &ControlMapSection,
+    new: &ControlMapSection,
+    changes: &mut Vec<Change>,
+) {
+    let mut new_entries: HashMap<&str, Vec<&ControlMapEntry>> = HashMap::new();
+    for entry in new.entries() {
+        new_entries.entry(&entry.event).or_default().push(entry);
+    }
+
+    for new_list in new_entries.values_mut() {
+        new_list.reverse();
+    }
+
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+
+    for old_entry in old.entries() {
+        let occurrence = occurrences.entry(&old_entry.event).or_insert(0);
+        let index = *occurrence;
+        *occurrence += 1;
+
+        let paired = new_entries
+            .get_mut(old_entry.event.as_str())
+            .and_then(|list| list.pop());
+
+        match paired {
+            Some(new_entry) => diff_entry(section, index, old_entry, new_entry, changes),
+            None => changes.push(Change {
+                section,
+                kind: ChangeKind::EventRemoved {
+                    event: old_entry.event.clone(),
+                },
+            }),
+        }
+    }
+
+    // Whatever wasn't paired up with an old entry is new, and should be
+    // reported in the order it appears in the new file.
+    for new_entry in new.entries() {
+        if let Some(list) = new_entries.get_mut(new_entry.event.as_str()) {
+            if list.last().is_some_and(|e| std::ptr::eq(*e, new_entry)) {
+                list.pop();
+                changes.push(Change {
+                    section,
+                    kind: ChangeKind::EventAdded {
+                        event: new_entry.event.clone(),
+                    },
+                });
+            }
+        }
+    }
+}
+
+fn diff_entry(
+    section: usize,
+    occurrence: usize,
+    old: &ControlMapEntry,
+    new: &ControlMapEntry,
+    changes: &mut Vec<Change>,
+) {
+    let event = &old.event;
+
+    for device in Device::all() {
+        if old.binding(device) != new.binding(device) {
+            changes.push(Change {
+                section,
+                kind: ChangeKind::BindingChanged {
+                    event: event.clone(),
+                    occurrence,
+                    device,
+                    old: old.binding(device).clone(),
+                    new: new.binding(device).clone(),
+                },
+            });
+        }
+    }
+
+    for device in Device::all() {
+        if old.mappable(device) != new.mappable(device) {
+            changes.push(Change {
+                section,
+                kind: ChangeKind::MappableChanged {
+                    event: event.clone(),
+                    occurrence,
+                    device,
+                    old: old.mappable(device),
+                    new: new.mappable(device),
+                },
+            });
+        }
+    }
+
+    if old.event_flag != new.event_flag {
+        changes.push(Change {
+            section,
+            kind: ChangeKind::EventFlagChanged {
+                event: event.clone(),
+                occurrence,
+                old: old.event_flag,
+                new: new.event_flag,
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_context::InputContext;
+    use crate::DEFAULT_CONTROLMAP;
+
+    #[test]
+    fn pairs_repeated_events_by_occurrence() {
+        let vanilla: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+        let mut map = vanilla.clone();
+        map.find_occurrence_mut(InputContext::Lockpicking as usize, "RotateLock", 1)
+            .unwrap()
+            .gamepad = "0x000b".parse().unwrap();
+
+        let changes = vanilla.diff(&map);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].section, InputContext::Lockpicking as usize);
+        assert_eq!(
+            changes[0].kind.to_string(),
+            "~ RotateLock#2: gamepad changed from RightStick to LeftStick"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! The user event flags in the optional eighth column of an entry. They put
+//! related events into groups, like all of the movement controls, so the game
+//! can turn a whole group on or off at once.
+
+use std::fmt::{self, Display};
+use std::ops::{BitOr, BitOrAssign};
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::format::parse_hex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EventFlags(u32);
+
+impl EventFlags {
+    pub const MOVEMENT: Self = Self(1 << 0);
+    pub const LOOKING: Self = Self(1 << 1);
+    pub const ACTIVATE: Self = Self(1 << 2);
+    pub const MENU: Self = Self(1 << 3);
+    pub const CONSOLE: Self = Self(1 << 4);
+    pub const POV_SWITCH: Self = Self(1 << 5);
+    pub const FIGHTING: Self = Self(1 << 6);
+    pub const SNEAKING: Self = Self(1 << 7);
+    pub const MAIN_FOUR: Self = Self(1 << 8);
+    pub const WHEEL_ZOOM: Self = Self(1 << 9);
+    pub const JUMPING: Self = Self(1 << 10);
+    pub const VATS: Self = Self(1 << 11);
+
+    /// Every flag the game knows about, with its canonical name and other
+    /// names accepted when parsing.
+    const NAMED: &'static [(Self, &'static str, &'static [&'static str])] = &[
+        (Self::MOVEMENT, "Movement", &[]),
+        (Self::LOOKING, "Looking", &["Look"]),
+        (Self::ACTIVATE, "Activate", &[]),
+        (Self::MENU, "Menu", &[]),
+        (Self::CONSOLE, "Console", &[]),
+        (Self::POV_SWITCH, "POVSwitch", &["POV"]),
+        (Self::FIGHTING, "Fighting", &[]),
+        (Self::SNEAKING, "Sneaking", &["Sneak"]),
+        (Self::MAIN_FOUR, "MainFour", &[]),
+        (Self::WHEEL_ZOOM, "WheelZoom", &[]),
+        (Self::JUMPING, "Jumping", &["Jump"]),
+        (Self::VATS, "VATS", &[]),
+    ];
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// The bits that are set but don't belong to any flag the game knows
+    /// about.
+    pub fn unknown_bits(self) -> u32 {
+        let known = Self::NAMED
+            .iter()
+            .fold(0, |known, (flag, _, _)| known | flag.0);
+
+        self.0 & !known
+    }
+
+    /// The canonical names of every known flag that's set, in bit order.
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMED
+            .iter()
+            .filter(|(flag, _, _)| self.0 & flag.0 != 0)
+            .map(|(_, name, _)| *name)
+            .collect()
+    }
+
+    /// Looks up a single flag by its canonical name or one of its aliases,
+    /// ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+
+        Self::NAMED
+            .iter()
+            .find(|(_, canonical, aliases)| {
+                canonical.eq_ignore_ascii_case(name)
+                    || aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+            })
+            .map(|(flag, _, _)| *flag)
+    }
+}
+
+impl BitOr for EventFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for EventFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// Writes the flags in hex, the way controlmap.txt does.
+impl fmt::LowerHex for EventFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+/// Writes the flags by name, like `Movement | VATS`. Unknown bits are written
+/// in hex at the end.
+impl Display for EventFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = self.names().into_iter().map(str::to_owned).collect();
+
+        let unknown = self.unknown_bits();
+        if unknown != 0 || parts.is_empty() {
+            parts.push(format!("{:#x}", unknown));
+        }
+
+        write!(f, "{}", parts.join(" | "))
+    }
+}
+
+/// Parses flags written by name, in hex, or as a mix of both, separated by
+/// `|` or `+`, like `Movement | VATS` or `0x801`.
+impl FromStr for EventFlags {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = EventFlags::default();
+
+        for part in s.split(['|', '+']) {
+            let part = part.trim();
+
+            if part.starts_with("0x") {
+                flags |= EventFlags(parse_hex(part)?);
+            } else if let Some(flag) = EventFlags::from_name(part) {
+                flags |= flag;
+            } else {
+                let names: Vec<&str> = Self::NAMED.iter().map(|(_, name, _)| *name).collect();
+                bail!(
+                    "Unknown event flag {}, expected a hex value or one of {}",
+                    part,
+                    names.join(", ")
+                );
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_names() {
+        let flags = EventFlags::MOVEMENT | EventFlags::VATS;
+
+        assert_eq!(flags.to_string(), "Movement | VATS");
+        assert_eq!("Movement | VATS".parse::<EventFlags>().unwrap(), flags);
+        assert_eq!("movement+vats".parse::<EventFlags>().unwrap(), flags);
+    }
+
+    #[test]
+    fn round_trips_hex() {
+        let flags: EventFlags = "0xC01".parse().unwrap();
+
+        assert_eq!(flags, EventFlags::from_bits(0xc01));
+        assert_eq!(format!("{:#x}", flags), "0xc01");
+        assert_eq!(flags.to_string(), "Movement | Jumping | VATS");
+        assert_eq!("Jump | 0x801".parse::<EventFlags>().unwrap(), flags);
+    }
+
+    #[test]
+    fn keeps_unknown_bits() {
+        let flags = EventFlags::from_bits(0x10008);
+
+        assert_eq!(flags.to_string(), "Menu | 0x10000");
+        assert_eq!(flags.to_string().parse::<EventFlags>().unwrap(), flags);
+        assert_eq!(EventFlags::default().to_string(), "0x0");
+        assert_eq!("0x0".parse::<EventFlags>().unwrap(), EventFlags::default());
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        let err = "Movement | Flying".parse::<EventFlags>().unwrap_err();
+
+        assert!(err
+            .to_string()
+            .starts_with("Unknown event flag Flying, expected a hex value or one of Movement"));
+    }
+}
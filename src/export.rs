@@ -0,0 +1,370 @@
+//! Converting controlmap files to and from other formats.
+//!
+//! The JSON format mirrors the structure of a controlmap file. Every section
+//! is tagged with the name of its input context, and every binding is written
+//! both as structured input codes and as the text used in controlmap.txt. The
+//! original text of every line is kept too, so importing an unedited export
+//! gives back exactly the same file.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::event_flags::EventFlags;
+use crate::format::{
+    Binding, ConflictSide, ControlMapEntry, ControlMapFile, ControlMapLine, ControlMapSection,
+    Device, Input,
+};
+use crate::input_codes::InputNames;
+use crate::input_context::InputContext;
+
+/// The formats that a controlmap file can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+
+    /// A printable cheat sheet, as Markdown tables.
+    Markdown,
+
+    /// A printable cheat sheet, as an HTML page.
+    Html,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            _ => bail!(
+                "Unknown export format {}, expected json, markdown, or html",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileJson {
+    line_ending: String,
+    trailing_newline: bool,
+    sections: Vec<SectionJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SectionJson {
+    /// The name of the section's input context, or nothing for sections the
+    /// game doesn't know about.
+    context: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    separator: Option<String>,
+
+    lines: Vec<LineJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum LineJson {
+    Comment {
+        text: String,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        raw: Option<String>,
+    },
+    Entry(Box<EntryJson>),
+    Conflict {
+        sides: Vec<ConflictSideJson>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConflictSideJson {
+    label: String,
+    entry: EntryJson,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryJson {
+    event: String,
+    keyboard: BindingJson,
+    mouse: BindingJson,
+    gamepad: BindingJson,
+    mappable: MappableJson,
+
+    /// The event flag in hex, like `0x801`. Flag names, like
+    /// `Movement | VATS`, are accepted when importing too.
+    flag: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MappableJson {
+    keyboard: bool,
+    mouse: bool,
+    gamepad: bool,
+}
+
+/// A binding for one device. Only `inputs` is read back in, and the rest is
+/// there for convenience.
+#[derive(Serialize, Deserialize)]
+struct BindingJson {
+    /// The binding as it's written in controlmap.txt.
+    #[serde(skip_deserializing)]
+    raw: String,
+
+    /// The binding written with key names, as accepted by `bind` with the same
+    /// layout and pad style.
+    #[serde(skip_deserializing)]
+    names: String,
+
+    inputs: Vec<InputJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InputJson {
+    /// Keys that all have to be pressed together.
+    Keys(Vec<KeyJson>),
+
+    Alias {
+        context: usize,
+
+        #[serde(default, skip_deserializing)]
+        context_name: Option<String>,
+
+        event: String,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyJson {
+    code: u32,
+
+    #[serde(default, skip_deserializing)]
+    name: Option<String>,
+}
+
+impl ControlMapFile {
+    /// Writes this file as pretty-printed JSON, naming keys with `names`.
+    pub fn to_json(&self, names: InputNames) -> String {
+        let file = FileJson {
+            line_ending: self.line_ending.to_owned(),
+            trailing_newline: self.trailing_newline,
+            sections: self
+                .sections
+                .iter()
+                .enumerate()
+                .map(|(section_id, section)| SectionJson {
+                    context: InputContext::from_u32(section_id as u32)
+                        .map(|context| context.name().to_owned()),
+                    separator: section.separator.clone(),
+                    lines: section
+                        .body
+                        .iter()
+                        .map(|line| line_to_json(line, names))
+                        .collect(),
+                })
+                .collect(),
+        };
+
+        serde_json::to_string_pretty(&file).expect("controlmaps can always be written as JSON")
+    }
+
+    /// Reads a file written by `to_json`.
+    pub fn from_json(s: &str) -> anyhow::Result<Self> {
+        let file: FileJson = serde_json::from_str(s)?;
+
+        let line_ending = match file.line_ending.as_str() {
+            "\n" => "\n",
+            "\r\n" => "\r\n",
+            other => bail!("Unsupported line ending {:?}", other),
+        };
+
+        let mut sections = Vec::new();
+        for (section_id, section) in file.sections.into_iter().enumerate() {
+            if let Some(name) = &section.context {
+                let context: InputContext = name.parse()?;
+                if context as usize != section_id {
+                    bail!(
+                        "Section {} is tagged as {}, which belongs in section {}",
+                        section_id,
+                        name,
+                        context as usize
+                    );
+                }
+            }
+
+            let body = section
+                .lines
+                .into_iter()
+                .map(line_from_json)
+                .collect::<anyhow::Result<_>>()
+                .with_context(|| format!("Error in section {}", section_id))?;
+
+            sections.push(ControlMapSection {
+                body,
+                separator: section.separator,
+            });
+        }
+
+        Ok(Self {
+            sections,
+            line_ending,
+            trailing_newline: file.trailing_newline,
+        })
+    }
+}
+
+fn line_to_json(line: &ControlMapLine, names: InputNames) -> LineJson {
+    match line {
+        ControlMapLine::Comment { text, raw } => LineJson::Comment {
+            text: text.clone(),
+            raw: raw.clone(),
+        },
+        ControlMapLine::Entry(entry) => LineJson::Entry(Box::new(entry_to_json(entry, names))),
+        ControlMapLine::Conflict(sides) => LineJson::Conflict {
+            sides: sides
+                .iter()
+                .map(|side| ConflictSideJson {
+                    label: side.label.clone(),
+                    entry: entry_to_json(&side.entry, names),
+                })
+                .collect(),
+        },
+    }
+}
+
+fn line_from_json(line: LineJson) -> anyhow::Result<ControlMapLine> {
+    Ok(match line {
+        LineJson::Comment { text, raw } => ControlMapLine::Comment { text, raw },
+        LineJson::Entry(entry) => ControlMapLine::Entry(entry_from_json(*entry)?),
+        LineJson::Conflict { sides } => ControlMapLine::Conflict(
+            sides
+                .into_iter()
+                .map(|side| {
+                    Ok(ConflictSide {
+                        label: side.label,
+                        entry: entry_from_json(side.entry)?,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+    })
+}
+
+fn entry_to_json(entry: &ControlMapEntry, names: InputNames) -> EntryJson {
+    EntryJson {
+        event: entry.event.clone(),
+        keyboard: binding_to_json(&entry.keyboard, Device::Keyboard, names),
+        mouse: binding_to_json(&entry.mouse, Device::Mouse, names),
+        gamepad: binding_to_json(&entry.gamepad, Device::Gamepad, names),
+        mappable: MappableJson {
+            keyboard: entry.keyboard_mappable,
+            mouse: entry.mouse_mappable,
+            gamepad: entry.gamepad_mappable,
+        },
+        flag: entry.event_flag.map(|flag| format!("{:#x}", flag)),
+        line: entry.line,
+        raw: entry.raw.clone(),
+    }
+}
+
+fn entry_from_json(entry: EntryJson) -> anyhow::Result<ControlMapEntry> {
+    let event_flag = entry
+        .flag
+        .as_deref()
+        .map(str::parse::<EventFlags>)
+        .transpose()
+        .with_context(|| format!("Invalid event flag for {}", entry.event))?;
+
+    // Columns that haven't changed are written back the way the original
+    // line had them.
+    let original = entry
+        .raw
+        .as_deref()
+        .and_then(|raw| raw.trim().parse::<ControlMapEntry>().ok())
+        .and_then(|parsed| parsed.original);
+
+    Ok(ControlMapEntry {
+        keyboard: binding_from_json(entry.keyboard),
+        mouse: binding_from_json(entry.mouse),
+        gamepad: binding_from_json(entry.gamepad),
+        keyboard_mappable: entry.mappable.keyboard,
+        mouse_mappable: entry.mappable.mouse,
+        gamepad_mappable: entry.mappable.gamepad,
+        event_flag,
+        line: entry.line,
+        raw: entry.raw,
+        original,
+        event: entry.event,
+    })
+}
+
+fn binding_to_json(binding: &Binding, device: Device, names: InputNames) -> BindingJson {
+    BindingJson {
+        raw: binding.to_string(),
+        names: device.format_binding(binding, names),
+        inputs: binding
+            .inputs
+            .iter()
+            .map(|input| match input {
+                Input::Inputs(codes) => InputJson::Keys(
+                    codes
+                        .iter()
+                        .map(|&code| KeyJson {
+                            code,
+                            name: device.named_code(code, names).map(str::to_owned),
+                        })
+                        .collect(),
+                ),
+                Input::Alias { context, event } => InputJson::Alias {
+                    context: *context,
+                    context_name: InputContext::from_u32(*context as u32)
+                        .map(|context| context.name().to_owned()),
+                    event: event.clone(),
+                },
+            })
+            .collect(),
+    }
+}
+
+fn binding_from_json(binding: BindingJson) -> Binding {
+    Binding {
+        inputs: binding
+            .inputs
+            .into_iter()
+            .map(|input| match input {
+                InputJson::Keys(keys) => {
+                    Input::Inputs(keys.into_iter().map(|key| key.code).collect())
+                }
+                InputJson::Alias { context, event, .. } => Input::Alias { context, event },
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_CONTROLMAP;
+
+    #[test]
+    fn round_trips_through_json() {
+        let map: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+
+        let json = map.to_json(InputNames::default());
+        let imported = ControlMapFile::from_json(&json).unwrap();
+
+        assert_eq!(imported.to_string_lossless(), DEFAULT_CONTROLMAP);
+        assert!(map.diff(&imported).is_empty());
+    }
+}
@@ -1,41 +1,164 @@
-use std::collections::HashSet;
 use std::fmt::{self, Display};
-use std::marker::PhantomData;
-use std::mem::take;
+use std::mem::{replace, take};
 use std::str::FromStr;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
+use serde::Deserialize;
 
-use crate::columnar::ColumnPrinter;
-use crate::input_codes::InputCode;
+use crate::columnar::{align_to, column_starts, ColumnPrinter};
+use crate::event_flags::EventFlags;
+use crate::input_codes::{Gamepad, InputCode, InputNames, Keyboard, Mouse};
+use crate::input_context::section_name;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ControlMapFile {
     pub sections: Vec<ControlMapSection>,
+
+    /// The line ending used by the file this was read from, either `\n` or
+    /// `\r\n`.
+    pub line_ending: &'static str,
+
+    /// Whether the file this was read from ended with a line ending.
+    pub trailing_newline: bool,
 }
 
 impl ControlMapFile {
-    pub fn remove_duplicates(&mut self) {
-        for section in &mut self.sections {
-            let mut visited = HashSet::new();
-
-            section.body = take(&mut section.body)
-                .into_iter()
-                .rev()
-                .filter(|line| match line {
-                    ControlMapLine::Comment(_) => true,
+    /// Writes this file back out while keeping the original layout. Lines
+    /// whose values haven't changed since they were read are written back
+    /// exactly as they were, and only edited or new entries are re-rendered,
+    /// lined up with the entries around them.
+    pub fn to_string_lossless(&self) -> String {
+        let mut lines = Vec::new();
+
+        for (i, section) in self.sections.iter().enumerate() {
+            if i > 0 {
+                lines.push(section.separator.clone().unwrap_or_default());
+            }
+
+            for (line_index, line) in section.body.iter().enumerate() {
+                match line {
+                    ControlMapLine::Comment { text, raw } => match raw {
+                        Some(raw) => lines.push(raw.clone()),
+                        None => lines.push(format!("// {}", text)),
+                    },
                     ControlMapLine::Entry(entry) => {
-                        if visited.contains(&entry.event) {
-                            false
+                        if let Some(raw) = entry.unchanged_raw() {
+                            lines.push(raw.to_owned());
                         } else {
-                            visited.insert(entry.event.clone());
-                            true
+                            lines.push(section.render_entry_near(line_index, entry));
                         }
                     }
-                })
-                .rev()
-                .collect();
+                    ControlMapLine::Conflict(sides) => {
+                        ConflictSide::write_all(sides, &mut lines, |entry| {
+                            section.render_entry_near(line_index, entry)
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut output = lines.join(self.line_ending);
+        if self.trailing_newline {
+            output.push_str(self.line_ending);
+        }
+
+        output
+    }
+
+    /// Swaps what two events in the same section are bound to on one device.
+    /// Swapping an event with itself changes nothing.
+    pub fn swap_bindings(
+        &mut self,
+        section: usize,
+        first: &str,
+        second: &str,
+        device: Device,
+    ) -> anyhow::Result<()> {
+        for event in [first, second] {
+            if self.find_entry_mut(section, event).is_none() {
+                bail!("No event named {} in {}", event, section_name(section));
+            }
+        }
+
+        if first == second {
+            return Ok(());
+        }
+
+        let first_binding = take(
+            self.find_entry_mut(section, first)
+                .unwrap()
+                .binding_mut(device),
+        );
+        let second_binding = replace(
+            self.find_entry_mut(section, second)
+                .unwrap()
+                .binding_mut(device),
+            first_binding,
+        );
+        *self
+            .find_entry_mut(section, first)
+            .unwrap()
+            .binding_mut(device) = second_binding;
+
+        Ok(())
+    }
+
+    /// Rewrites every alias on the given device that points at one of the two
+    /// events so that it points at the other one instead. After swapping the
+    /// events' bindings, this keeps the aliases on the inputs they had
+    /// before. Returns how many aliases were changed.
+    pub fn swap_aliases(
+        &mut self,
+        section: usize,
+        first: &str,
+        second: &str,
+        device: Device,
+    ) -> usize {
+        if first == second {
+            return 0;
+        }
+
+        let mut changed = 0;
+
+        for entry in self.sections.iter_mut().flat_map(|s| s.entries_mut()) {
+            for input in &mut entry.binding_mut(device).inputs {
+                if let Input::Alias { context, event } = input {
+                    if *context != section {
+                        continue;
+                    }
+
+                    if event == first {
+                        *event = second.to_owned();
+                        changed += 1;
+                    } else if event == second {
+                        *event = first.to_owned();
+                        changed += 1;
+                    }
+                }
+            }
         }
+
+        changed
+    }
+
+    /// Finds the first entry for the given event in the given section.
+    pub fn find_entry_mut(&mut self, section: usize, event: &str) -> Option<&mut ControlMapEntry> {
+        self.find_occurrence_mut(section, event, 0)
+    }
+
+    /// Finds an entry for the given event in the given section, counting
+    /// from zero when the event appears more than once.
+    pub fn find_occurrence_mut(
+        &mut self,
+        section: usize,
+        event: &str,
+        occurrence: usize,
+    ) -> Option<&mut ControlMapEntry> {
+        self.sections
+            .get_mut(section)?
+            .entries_mut()
+            .filter(|entry| entry.event == event)
+            .nth(occurrence)
     }
 }
 
@@ -45,27 +168,33 @@ impl FromStr for ControlMapFile {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut sections = vec![ControlMapSection::new()];
 
-        for (i, line) in s.lines().enumerate() {
-            let line = line.trim();
+        for (i, raw) in s.lines().enumerate() {
+            let line = raw.trim();
 
             if line.is_empty() && !sections.last().unwrap().body.is_empty() {
-                sections.push(ControlMapSection::new());
+                let mut section = ControlMapSection::new();
+                section.separator = Some(raw.to_owned());
+                sections.push(section);
                 continue;
             }
 
-            if line.starts_with("//") {
-                let comment = (&line[2..]).trim_start().to_owned();
+            if let Some(comment) = line.strip_prefix("//") {
                 sections
                     .last_mut()
                     .unwrap()
                     .body
-                    .push(ControlMapLine::Comment(comment));
+                    .push(ControlMapLine::Comment {
+                        text: comment.trim_start().to_owned(),
+                        raw: Some(raw.to_owned()),
+                    });
                 continue;
             }
 
-            let parsed: ControlMapEntry = line
+            let mut parsed: ControlMapEntry = line
                 .parse()
                 .with_context(|| format!("Error on line {}", i + 1))?;
+            parsed.line = Some(i + 1);
+            parsed.raw = Some(raw.to_owned());
             sections
                 .last_mut()
                 .unwrap()
@@ -73,7 +202,13 @@ impl FromStr for ControlMapFile {
                 .push(ControlMapLine::Entry(parsed));
         }
 
-        Ok(Self { sections })
+        let line_ending = if s.contains("\r\n") { "\r\n" } else { "\n" };
+
+        Ok(Self {
+            sections,
+            line_ending,
+            trailing_newline: s.ends_with('\n'),
+        })
     }
 }
 
@@ -91,9 +226,12 @@ impl Display for ControlMapFile {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ControlMapSection {
     pub body: Vec<ControlMapLine>,
+
+    /// The blank line that started this section, exactly as it was read.
+    pub separator: Option<String>,
 }
 
 impl Display for ControlMapSection {
@@ -102,23 +240,40 @@ impl Display for ControlMapSection {
 
         for line in &self.body {
             match line {
-                ControlMapLine::Comment(text) => {
-                    printer.finish(&mut f)?;
+                ControlMapLine::Comment { text, .. } => {
+                    finish_rows(printer, &mut f)?;
                     printer = ColumnPrinter::new();
                     writeln!(f, "// {}", text)?;
                 }
                 ControlMapLine::Entry(entry) => {
                     printer.row();
-                    printer.add(&entry.event);
-                    printer.add(&entry.keyboard);
-                    printer.add(&entry.mouse);
-                    printer.add(&entry.gamepad);
-                    printer.add(entry.keyboard_mappable as u8);
-                    printer.add(entry.mouse_mappable as u8);
-                    printer.add(entry.gamepad_mappable as u8);
-
-                    if let Some(flag) = entry.event_flag {
-                        printer.add(format_args!("{:#x}", flag));
+
+                    for field in entry.fields() {
+                        printer.add(field);
+                    }
+                }
+                ControlMapLine::Conflict(sides) => {
+                    finish_rows(printer, &mut f)?;
+                    printer = ColumnPrinter::new();
+
+                    let mut lines = Vec::new();
+                    ConflictSide::write_all(sides, &mut lines, |entry| {
+                        let mut side_printer = ColumnPrinter::new();
+                        side_printer.row();
+
+                        for field in entry.fields() {
+                            side_printer.add(field);
+                        }
+
+                        let mut output = String::new();
+                        side_printer
+                            .finish(&mut output)
+                            .expect("writing to a String cannot fail");
+                        output
+                    });
+
+                    for line in lines {
+                        writeln!(f, "{}", line)?;
                     }
                 }
             }
@@ -130,19 +285,116 @@ impl Display for ControlMapSection {
     }
 }
 
+/// Writes out any rows collected so far, ending the last one with a newline
+/// so that whatever follows starts on its own line.
+fn finish_rows(printer: ColumnPrinter, mut f: impl fmt::Write) -> fmt::Result {
+    if printer.is_empty() {
+        return Ok(());
+    }
+
+    printer.finish(&mut f)?;
+    writeln!(f)
+}
+
 impl ControlMapSection {
     fn new() -> Self {
-        Self { body: Vec::new() }
+        Self {
+            body: Vec::new(),
+            separator: None,
+        }
+    }
+
+    /// Renders an entry that has no usable original text, lining its columns
+    /// up with the closest unchanged entry to the given position.
+    fn render_entry_near(&self, index: usize, entry: &ControlMapEntry) -> String {
+        fn neighbor(line: &ControlMapLine) -> Option<&str> {
+            match line {
+                ControlMapLine::Entry(other) => other.unchanged_raw(),
+                ControlMapLine::Comment { .. } | ControlMapLine::Conflict(_) => None,
+            }
+        }
+
+        let before = self.body[..index].iter().rev().find_map(neighbor);
+        let after = self.body[index + 1..].iter().find_map(neighbor);
+
+        match before.or(after) {
+            Some(reference) => align_to(
+                &entry.fields_preserving_original(),
+                &column_starts(reference),
+            ),
+            None => {
+                let mut printer = ColumnPrinter::new();
+                printer.row();
+
+                for field in entry.fields_preserving_original() {
+                    printer.add(field);
+                }
+
+                let mut output = String::new();
+                printer
+                    .finish(&mut output)
+                    .expect("writing to a String cannot fail");
+                output
+            }
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &ControlMapEntry> {
+        self.body.iter().filter_map(|line| match line {
+            ControlMapLine::Entry(entry) => Some(entry),
+            ControlMapLine::Comment { .. } | ControlMapLine::Conflict(_) => None,
+        })
+    }
+
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut ControlMapEntry> {
+        self.body.iter_mut().filter_map(|line| match line {
+            ControlMapLine::Entry(entry) => Some(entry),
+            ControlMapLine::Comment { .. } | ControlMapLine::Conflict(_) => None,
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ControlMapLine {
-    Comment(String),
+    Comment {
+        text: String,
+
+        /// The whole line, exactly as it was read.
+        raw: Option<String>,
+    },
     Entry(ControlMapEntry),
+
+    /// Competing versions of an entry left behind by a merge. These are
+    /// written out between conflict markers for someone to resolve by hand.
+    Conflict(Vec<ConflictSide>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct ConflictSide {
+    /// Where this version of the entry came from, usually a file path.
+    pub label: String,
+    pub entry: ControlMapEntry,
+}
+
+impl ConflictSide {
+    /// Writes out every side of a conflict between markers, starting each
+    /// side with a marker naming where it came from.
+    fn write_all(
+        sides: &[ConflictSide],
+        lines: &mut Vec<String>,
+        mut render: impl FnMut(&ControlMapEntry) -> String,
+    ) {
+        for (i, side) in sides.iter().enumerate() {
+            let marker = if i == 0 { "<<<<<<<" } else { "=======" };
+            lines.push(format!("{} {}", marker, side.label));
+            lines.push(render(&side.entry));
+        }
+
+        lines.push(">>>>>>>".to_owned());
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ControlMapEntry {
     pub event: String,
     pub keyboard: Binding,
@@ -151,14 +403,123 @@ pub struct ControlMapEntry {
     pub keyboard_mappable: bool,
     pub mouse_mappable: bool,
     pub gamepad_mappable: bool,
-    pub event_flag: Option<u32>,
+    pub event_flag: Option<EventFlags>,
+
+    /// The line this entry was read from, if it came from a file.
+    pub line: Option<usize>,
+
+    /// The text of the line this entry was read from, exactly as it was read.
+    pub raw: Option<String>,
+
+    /// The columns this entry was parsed from, if it was parsed from text.
+    pub original: Option<OriginalColumns>,
+}
+
+/// The columns of the text an entry was parsed from, so that columns whose
+/// values haven't changed can be written back exactly as they were, like
+/// `0xC01` instead of `0xc01`.
+#[derive(Debug, Clone)]
+pub struct OriginalColumns {
+    /// Each column as it was written.
+    written: Vec<String>,
+
+    /// Each column as `ControlMapEntry::fields` wrote it right after parsing.
+    values: Vec<String>,
+}
+
+impl ControlMapEntry {
+    /// The values of this entry's columns, as they're written in a file.
+    pub fn fields(&self) -> Vec<String> {
+        let mut fields = vec![
+            self.event.clone(),
+            self.keyboard.to_string(),
+            self.mouse.to_string(),
+            self.gamepad.to_string(),
+            (self.keyboard_mappable as u8).to_string(),
+            (self.mouse_mappable as u8).to_string(),
+            (self.gamepad_mappable as u8).to_string(),
+        ];
+
+        if let Some(flag) = self.event_flag {
+            fields.push(format!("{:#x}", flag));
+        }
+
+        fields
+    }
+
+    /// Returns the original text of this entry, as long as it still describes
+    /// the same values the entry has now.
+    pub fn unchanged_raw(&self) -> Option<&str> {
+        let raw = self.raw.as_deref()?;
+        let original = self.original.as_ref()?;
+
+        if original.values == self.fields() {
+            Some(raw)
+        } else {
+            None
+        }
+    }
+
+    /// Like `fields`, but columns whose values haven't changed since this
+    /// entry was parsed are written the way they originally were.
+    pub fn fields_preserving_original(&self) -> Vec<String> {
+        let mut fields = self.fields();
+
+        if let Some(original) = &self.original {
+            for (i, field) in fields.iter_mut().enumerate() {
+                if original.values.get(i) == Some(field) {
+                    *field = original.written[i].clone();
+                }
+            }
+        }
+
+        fields
+    }
+
+    pub fn binding(&self, device: Device) -> &Binding {
+        match device {
+            Device::Keyboard => &self.keyboard,
+            Device::Mouse => &self.mouse,
+            Device::Gamepad => &self.gamepad,
+        }
+    }
+
+    pub fn binding_mut(&mut self, device: Device) -> &mut Binding {
+        match device {
+            Device::Keyboard => &mut self.keyboard,
+            Device::Mouse => &mut self.mouse,
+            Device::Gamepad => &mut self.gamepad,
+        }
+    }
+
+    /// Tells whether players can remap this event on the given device.
+    pub fn mappable(&self, device: Device) -> bool {
+        match device {
+            Device::Keyboard => self.keyboard_mappable,
+            Device::Mouse => self.mouse_mappable,
+            Device::Gamepad => self.gamepad_mappable,
+        }
+    }
+
+    pub fn mappable_mut(&mut self, device: Device) -> &mut bool {
+        match device {
+            Device::Keyboard => &mut self.keyboard_mappable,
+            Device::Mouse => &mut self.mouse_mappable,
+            Device::Gamepad => &mut self.gamepad_mappable,
+        }
+    }
 }
 
 impl FromStr for ControlMapEntry {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut pieces = s.split('\t').filter(|s| !s.is_empty());
+        let written: Vec<String> = s
+            .split('\t')
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned)
+            .collect();
+        let mut pieces = written.iter().map(String::as_str);
         let event = pieces
             .next()
             .context("Missing event name (first value)")?
@@ -198,12 +559,12 @@ impl FromStr for ControlMapEntry {
             != 0;
         let event_flag = match pieces.next() {
             None => None,
-            Some(flag) => {
-                Some(parse_hex(flag).context("Invalid event binary flag (eigth value, optional)")?)
-            }
+            Some(flag) => Some(EventFlags::from_bits(
+                parse_hex(flag).context("Invalid event binary flag (eigth value, optional)")?,
+            )),
         };
 
-        Ok(Self {
+        let mut entry = Self {
             event,
             keyboard,
             mouse,
@@ -212,19 +573,60 @@ impl FromStr for ControlMapEntry {
             mouse_mappable,
             gamepad_mappable,
             event_flag,
-        })
+            line: None,
+            raw: None,
+            original: None,
+        };
+        entry.original = Some(OriginalColumns {
+            values: entry.fields(),
+            written,
+        });
+
+        Ok(entry)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Binding {
     pub inputs: Vec<Input>,
 }
 
-impl FromStr for Binding {
-    type Err = anyhow::Error;
+impl Binding {
+    /// Removes every alternative that matches one of the alternatives in
+    /// `filter`, returning how many were removed.
+    pub fn remove_matching(&mut self, filter: &Binding) -> usize {
+        let before = self.inputs.len();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        self.inputs
+            .retain(|input| !filter.inputs.iter().any(|other| input.matches(other)));
+
+        before - self.inputs.len()
+    }
+
+    /// Parses a binding written with key names, like `LeftControl+E` or
+    /// `A,RightShoulder`, looking up each name with `lookup`. Raw hex codes
+    /// and aliases are accepted too, using the same syntax as controlmap.txt.
+    /// `none` means the binding is empty.
+    pub fn from_names(s: &str, lookup: impl Fn(&str) -> Option<u32>) -> anyhow::Result<Self> {
+        if s.trim().eq_ignore_ascii_case("none") {
+            return Ok(Self::default());
+        }
+
+        Self::parse_with(s, |code| {
+            let code = code.trim();
+
+            if code.starts_with("0x") {
+                return parse_hex(code);
+            }
+
+            lookup(code).with_context(|| format!("Unknown key name {}", code))
+        })
+    }
+
+    fn parse_with(
+        s: &str,
+        mut parse_code: impl FnMut(&str) -> anyhow::Result<u32>,
+    ) -> anyhow::Result<Self> {
         let mut inputs = Vec::new();
         let mut pieces = s.split(',');
 
@@ -234,11 +636,11 @@ impl FromStr for Binding {
                 continue;
             }
 
-            if piece.starts_with("!") {
+            if let Some(context) = piece.strip_prefix('!') {
                 // This prefix indicates that the rest of this value is an input
                 // context ID and the next value should be used as an alias.
 
-                let context = (&piece[1..])
+                let context = context
                     .parse()
                     .context("Invalid binding: expected input context ID after !")?;
 
@@ -253,7 +655,7 @@ impl FromStr for Binding {
 
             let codes = piece
                 .split('+')
-                .map(parse_hex)
+                .map(&mut parse_code)
                 .collect::<Result<_, _>>()
                 .context("Invalid binding")?;
 
@@ -264,6 +666,14 @@ impl FromStr for Binding {
     }
 }
 
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(s, parse_hex)
+    }
+}
+
 impl Display for Binding {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.inputs.is_empty() {
@@ -297,13 +707,154 @@ impl Display for Binding {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Input {
     Inputs(Vec<u32>),
     Alias { context: usize, event: String },
 }
 
-fn parse_hex(input: &str) -> anyhow::Result<u32> {
+impl Input {
+    /// Tells whether two inputs refer to the same thing. Unlike `==`, the
+    /// order of keys in a chord doesn't matter.
+    pub fn matches(&self, other: &Input) -> bool {
+        match (self, other) {
+            (Input::Inputs(a), Input::Inputs(b)) => {
+                let mut a = a.clone();
+                let mut b = b.clone();
+                a.sort_unstable();
+                b.sort_unstable();
+                a == b
+            }
+            _ => self == other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Device {
+    Keyboard,
+    Mouse,
+    Gamepad,
+}
+
+impl Device {
+    pub fn all() -> [Device; 3] {
+        [Device::Keyboard, Device::Mouse, Device::Gamepad]
+    }
+
+    /// Looks up the name of an input code on this device.
+    pub fn code_name(self, code: u32) -> Option<&'static str> {
+        match self {
+            Device::Keyboard => Keyboard::from_u32(code).map(|key| key.name()),
+            Device::Mouse => Mouse::from_u32(code).map(|key| key.name()),
+            Device::Gamepad => Gamepad::from_u32(code).map(|key| key.name()),
+        }
+    }
+
+    /// The codes in a binding that the game's controls menu can't remap
+    /// events to, so players can't get back to them after rebinding.
+    pub fn unremappable_codes(self, binding: &Binding) -> Vec<u32> {
+        let remappable = |code| match self {
+            Device::Mouse => Mouse::from_u32(code).is_none_or(Mouse::is_remappable),
+            Device::Keyboard | Device::Gamepad => true,
+        };
+
+        binding
+            .inputs
+            .iter()
+            .filter_map(|input| match input {
+                Input::Inputs(codes) => Some(codes),
+                Input::Alias { .. } => None,
+            })
+            .flatten()
+            .copied()
+            .filter(|&code| !remappable(code))
+            .collect()
+    }
+
+    /// Looks up the name of an input code on this device, naming keys by the
+    /// given keyboard layout and gamepad style.
+    pub fn named_code(self, code: u32, names: InputNames) -> Option<&'static str> {
+        match self {
+            Device::Keyboard => Keyboard::from_u32(code).map(|key| names.layout.name(key)),
+            Device::Mouse => self.code_name(code),
+            Device::Gamepad => Gamepad::from_u32(code).map(|button| names.pad_style.name(button)),
+        }
+    }
+
+    /// Writes a binding using key names in a form that `parse_binding` can
+    /// read back with the same names. Codes without a name are written in
+    /// hex.
+    pub fn format_binding(self, binding: &Binding, names: InputNames) -> String {
+        if binding.inputs.is_empty() {
+            return "none".to_owned();
+        }
+
+        let alternatives: Vec<_> = binding
+            .inputs
+            .iter()
+            .map(|input| match input {
+                Input::Alias { context, event } => format!("!{},{}", context, event),
+                Input::Inputs(codes) => codes
+                    .iter()
+                    .map(|&code| match self.named_code(code, names) {
+                        Some(name) => name.to_owned(),
+                        None => format!("{:#04x}", code),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("+"),
+            })
+            .collect();
+
+        alternatives.join(",")
+    }
+
+    /// Describes a binding for this device using key names.
+    pub fn describe(self, binding: &Binding, names: InputNames) -> String {
+        PrettyPrintBinding::new(binding, self, names).to_string()
+    }
+
+    /// Parses a binding written with key names for this device.
+    pub fn parse_binding(self, s: &str, names: InputNames) -> anyhow::Result<Binding> {
+        match self {
+            Device::Keyboard => {
+                Binding::from_names(s, |name| names.layout.key(name).map(|key| key.to_u32()))
+            }
+            Device::Mouse => {
+                Binding::from_names(s, |name| Mouse::from_name(name).map(|key| key.to_u32()))
+            }
+            Device::Gamepad => Binding::from_names(s, |name| {
+                names.pad_style.button(name).map(|key| key.to_u32())
+            }),
+        }
+    }
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Device::Keyboard => write!(f, "keyboard"),
+            Device::Mouse => write!(f, "mouse"),
+            Device::Gamepad => write!(f, "gamepad"),
+        }
+    }
+}
+
+impl FromStr for Device {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "keyboard" => Ok(Device::Keyboard),
+            "mouse" => Ok(Device::Mouse),
+            "gamepad" => Ok(Device::Gamepad),
+            _ => bail!("Unknown device {}, expected keyboard, mouse, or gamepad", s),
+        }
+    }
+}
+
+pub fn parse_hex(input: &str) -> anyhow::Result<u32> {
     let body = input
         .strip_prefix("0x")
         .with_context(|| format!("Invalid hex value {}, missing 0x prefix", input))?;
@@ -311,21 +862,23 @@ fn parse_hex(input: &str) -> anyhow::Result<u32> {
     Ok(value)
 }
 
-pub struct PrettyPrintBinding<'a, I> {
+pub struct PrettyPrintBinding<'a> {
     binding: &'a Binding,
-    _marker: PhantomData<*const I>,
+    device: Device,
+    names: InputNames,
 }
 
-impl<'a, I: InputCode> PrettyPrintBinding<'a, I> {
-    pub fn new(binding: &'a Binding) -> Self {
+impl<'a> PrettyPrintBinding<'a> {
+    pub fn new(binding: &'a Binding, device: Device, names: InputNames) -> Self {
         Self {
             binding,
-            _marker: PhantomData,
+            device,
+            names,
         }
     }
 }
 
-impl<'a, I: InputCode> Display for PrettyPrintBinding<'a, I> {
+impl<'a> Display for PrettyPrintBinding<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.binding.inputs.is_empty() {
             write!(f, "<nothing>")?;
@@ -336,8 +889,8 @@ impl<'a, I: InputCode> Display for PrettyPrintBinding<'a, I> {
                 Input::Alias { context, event } => write!(f, "[{},{}]", context, event)?,
                 Input::Inputs(values) => {
                     for (i, value) in values.iter().enumerate() {
-                        if let Some(decoded) = I::from_u32(*value) {
-                            write!(f, "{:?}", decoded)?;
+                        if let Some(name) = self.device.named_code(*value, self.names) {
+                            write!(f, "{}", name)?;
                         } else {
                             write!(f, "<unknown>")?;
                         }
@@ -357,3 +910,179 @@ impl<'a, I: InputCode> Display for PrettyPrintBinding<'a, I> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_context::InputContext;
+    use crate::DEFAULT_CONTROLMAP;
+
+    const MAIN: usize = InputContext::MainGameplay as usize;
+
+    fn vanilla() -> ControlMapFile {
+        DEFAULT_CONTROLMAP.parse().unwrap()
+    }
+
+    fn gamepad_binding(map: &mut ControlMapFile, event: &str) -> Binding {
+        map.find_entry_mut(MAIN, event).unwrap().gamepad.clone()
+    }
+
+    #[test]
+    fn round_trips_unedited_files_exactly() {
+        assert_eq!(vanilla().to_string_lossless(), DEFAULT_CONTROLMAP);
+    }
+
+    #[test]
+    fn editing_one_column_keeps_the_others_as_written() {
+        let mut map = vanilla();
+        map.find_entry_mut(MAIN, "Jump").unwrap().keyboard = Binding {
+            inputs: vec![Input::Inputs(vec![Keyboard::X.to_u32()])],
+        };
+        map.find_entry_mut(InputContext::Book as usize, "PrevPage")
+            .unwrap()
+            .keyboard = Binding {
+            inputs: vec![Input::Inputs(vec![Keyboard::Home.to_u32()])],
+        };
+
+        let expected = DEFAULT_CONTROLMAP
+            .replacen(
+                "Jump\t\t\t\t0x39\t\t\t\t0xff\t0x8000\t\t\t\t1\t1\t1\t0xC01",
+                "Jump\t\t\t\t0x2d\t\t\t\t0xff\t0x8000\t\t\t\t1\t1\t1\t0xC01",
+                1,
+            )
+            .replacen(
+                "PrevPage\t\t\t0xcb\t\t\t\t0x0,0x9\t\t0x000c",
+                "PrevPage\t\t\t0xc7\t\t\t\t0x0,0x9\t\t0x000c",
+                1,
+            );
+        assert_ne!(expected, DEFAULT_CONTROLMAP);
+        assert_eq!(map.to_string_lossless(), expected);
+    }
+
+    #[test]
+    fn swaps_bindings() {
+        let mut map = vanilla();
+        let jump = gamepad_binding(&mut map, "Jump");
+        let ready_weapon = gamepad_binding(&mut map, "Ready Weapon");
+
+        map.swap_bindings(MAIN, "Jump", "Ready Weapon", Device::Gamepad)
+            .unwrap();
+
+        assert_eq!(gamepad_binding(&mut map, "Jump"), ready_weapon);
+        assert_eq!(gamepad_binding(&mut map, "Ready Weapon"), jump);
+    }
+
+    #[test]
+    fn swapping_an_event_with_itself_changes_nothing() {
+        let mut map = vanilla();
+
+        map.swap_bindings(MAIN, "Jump", "Jump", Device::Gamepad)
+            .unwrap();
+
+        assert_eq!(map.swap_aliases(MAIN, "Jump", "Jump", Device::Gamepad), 0);
+        assert_eq!(map.to_string_lossless(), DEFAULT_CONTROLMAP);
+    }
+
+    #[test]
+    fn swapping_a_missing_event_names_the_context() {
+        let mut map = vanilla();
+
+        let err = map
+            .swap_bindings(MAIN, "Jump", "Fly", Device::Gamepad)
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "No event named Fly in MainGameplay");
+    }
+
+    fn parse(device: Device, s: &str) -> Binding {
+        device.parse_binding(s, InputNames::default()).unwrap()
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(
+            parse(Device::Keyboard, "E"),
+            Binding {
+                inputs: vec![Input::Inputs(vec![Keyboard::E.to_u32()])]
+            }
+        );
+        assert_eq!(
+            parse(Device::Gamepad, " lb "),
+            Binding {
+                inputs: vec![Input::Inputs(vec![Gamepad::LeftShoulder.to_u32()])]
+            }
+        );
+        assert_eq!(
+            parse(Device::Keyboard, "0x12"),
+            parse(Device::Keyboard, "E")
+        );
+        assert!(Device::Keyboard
+            .parse_binding("Hyper", InputNames::default())
+            .is_err());
+    }
+
+    #[test]
+    fn parses_none_as_unbound() {
+        let binding = parse(Device::Mouse, "None");
+
+        assert!(binding.inputs.is_empty());
+        assert_eq!(binding.to_string(), "0xff");
+    }
+
+    #[test]
+    fn parses_chords_and_alternatives() {
+        let binding = parse(Device::Keyboard, "LeftControl+E,F,!0,Activate");
+
+        assert_eq!(
+            binding.inputs,
+            [
+                Input::Inputs(vec![Keyboard::LeftControl.to_u32(), Keyboard::E.to_u32()]),
+                Input::Inputs(vec![Keyboard::F.to_u32()]),
+                Input::Alias {
+                    context: 0,
+                    event: "Activate".to_owned()
+                },
+            ]
+        );
+        assert_eq!(binding.to_string(), "0x1d+0x12,0x21,!0,Activate");
+        assert_eq!(
+            Device::Keyboard.format_binding(&binding, InputNames::default()),
+            "LeftControl+E,F,!0,Activate"
+        );
+    }
+
+    #[test]
+    fn unbinds_matching_alternatives() {
+        let mut binding = parse(Device::Keyboard, "LeftControl+E,F,G");
+
+        let removed = binding.remove_matching(&parse(Device::Keyboard, "E+LeftControl,G,H"));
+
+        assert_eq!(removed, 2);
+        assert_eq!(binding, parse(Device::Keyboard, "F"));
+    }
+
+    #[test]
+    fn unbinding_one_alternative_keeps_the_rest() {
+        let mut map = vanilla();
+        let entry = map.find_entry_mut(MAIN, "Hotkey1").unwrap();
+
+        let filter = parse(Device::Keyboard, "Num1");
+        assert_eq!(
+            entry.binding_mut(Device::Keyboard).remove_matching(&filter),
+            1
+        );
+        assert_eq!(
+            entry.binding_mut(Device::Keyboard).remove_matching(&filter),
+            0
+        );
+
+        assert_eq!(
+            map.to_string_lossless(),
+            DEFAULT_CONTROLMAP.replacen(
+                "Hotkey1\t\t\t\t0x02,0x4f\t\t\t",
+                "Hotkey1\t\t\t\t0x02\t\t\t\t",
+                1
+            )
+        );
+    }
+}
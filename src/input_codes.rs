@@ -1,12 +1,40 @@
 use std::fmt::Debug;
+use std::str::FromStr;
 
-pub trait InputCode: Debug + Sized {
+use anyhow::bail;
+use structopt::StructOpt;
+
+pub trait InputCode: Debug + Copy + Sized + 'static {
     fn from_u32(value: u32) -> Option<Self>;
+    fn to_u32(&self) -> u32;
+
+    /// Every known code of this type, in declaration order.
+    fn all() -> &'static [Self];
+
+    /// The canonical name of this code, which is also used when printing it.
+    fn name(&self) -> &'static str;
+
+    /// Other names accepted for this code when parsing.
+    fn aliases(&self) -> &'static [&'static str];
+
+    /// Looks up a code by its canonical name or one of its aliases, ignoring
+    /// case.
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+
+        Self::all().iter().copied().find(|code| {
+            code.name().eq_ignore_ascii_case(name)
+                || code
+                    .aliases()
+                    .iter()
+                    .any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+    }
 }
 
 macro_rules! input_code {
-    ($struct_name:ident { $($name:ident = $code:literal,)* }) => {
-        #[derive(Debug)]
+    ($struct_name:ident { $($name:ident = $code:literal $(| $alias:literal)*,)* }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         pub enum $struct_name {
             $( $name = $code, )*
         }
@@ -18,24 +46,45 @@ macro_rules! input_code {
                     _ => None
                 }
             }
+
+            fn to_u32(&self) -> u32 {
+                *self as u32
+            }
+
+            fn all() -> &'static [Self] {
+                &[ $( Self::$name, )* ]
+            }
+
+            fn name(&self) -> &'static str {
+                match self {
+                    $( Self::$name => stringify!($name), )*
+                }
+            }
+
+            fn aliases(&self) -> &'static [&'static str] {
+                match self {
+                    $( Self::$name => &[ $( $alias, )* ], )*
+                }
+            }
         }
     };
 }
 
+// The DirectInput keyboard scancodes, DIK_* in dinput.h.
 input_code!(Keyboard {
-    Escape = 0x01,
-    One = 0x02,
-    Two = 0x03,
-    Three = 0x04,
-    Four = 0x05,
-    Five = 0x06,
-    Six = 0x07,
-    Seven = 0x08,
-    Eight = 0x09,
-    Nine = 0x0A,
-    Zero = 0x0B,
-    Minus = 0x0C,
-    Equals = 0x0D,
+    Escape = 0x01 | "Esc",
+    One = 0x02 | "1",
+    Two = 0x03 | "2",
+    Three = 0x04 | "3",
+    Four = 0x05 | "4",
+    Five = 0x06 | "5",
+    Six = 0x07 | "6",
+    Seven = 0x08 | "7",
+    Eight = 0x09 | "8",
+    Nine = 0x0A | "9",
+    Zero = 0x0B | "0",
+    Minus = 0x0C | "-",
+    Equals = 0x0D | "=",
     Backspace = 0x0E,
     Tab = 0x0F,
     Q = 0x10,
@@ -48,10 +97,10 @@ input_code!(Keyboard {
     I = 0x17,
     O = 0x18,
     P = 0x19,
-    LeftBracket = 0x1A,
-    RightBracket = 0x1B,
-    Enter = 0x1C,
-    LeftControl = 0x1D,
+    LeftBracket = 0x1A | "[",
+    RightBracket = 0x1B | "]",
+    Enter = 0x1C | "Return",
+    LeftControl = 0x1D | "LCtrl" | "LeftCtrl" | "Ctrl" | "Control",
     A = 0x1E,
     S = 0x1F,
     D = 0x20,
@@ -61,11 +110,11 @@ input_code!(Keyboard {
     J = 0x24,
     K = 0x25,
     L = 0x26,
-    Semicolon = 0x27,
-    Apostrophe = 0x28,
-    Backtick = 0x29,
-    LeftShift = 0x2A,
-    BackSlash = 0x2B,
+    Semicolon = 0x27 | ";",
+    Apostrophe = 0x28 | "Quote" | "'",
+    Backtick = 0x29 | "Grave" | "Tilde" | "`",
+    LeftShift = 0x2A | "LShift" | "Shift",
+    BackSlash = 0x2B | "\\",
     Z = 0x2C,
     X = 0x2D,
     C = 0x2E,
@@ -74,13 +123,13 @@ input_code!(Keyboard {
     N = 0x31,
     M = 0x32,
     Comma = 0x33,
-    Period = 0x34,
-    ForwardSlash = 0x35,
-    RightShift = 0x36,
-    NumpadStar = 0x37,
-    LeftAlt = 0x38,
-    Spacebar = 0x39,
-    CapsLock = 0x3A,
+    Period = 0x34 | "Dot" | ".",
+    ForwardSlash = 0x35 | "Slash" | "/",
+    RightShift = 0x36 | "RShift",
+    NumpadStar = 0x37 | "NumStar" | "NumMultiply",
+    LeftAlt = 0x38 | "LAlt" | "Alt",
+    Spacebar = 0x39 | "Space",
+    CapsLock = 0x3A | "Caps",
     F1 = 0x3B,
     F2 = 0x3C,
     F3 = 0x3D,
@@ -106,54 +155,364 @@ input_code!(Keyboard {
     Num3 = 0x51,
     Num0 = 0x52,
     NumPeriod = 0x53,
+    IntlBackslash = 0x56 | "Oem102" | "<>",
     F11 = 0x57,
     F12 = 0x58,
+    F13 = 0x64,
+    F14 = 0x65,
+    F15 = 0x66,
+    Kana = 0x70,
+    AbntC1 = 0x73 | "IntlRo",
+    Convert = 0x79,
+    NoConvert = 0x7B,
+    Yen = 0x7D | "IntlYen",
+    AbntC2 = 0x7E,
+    NumEquals = 0x8D,
+    PrevTrack = 0x90 | "Circumflex",
+    At = 0x91 | "@",
+    Colon = 0x92 | ":",
+    Underline = 0x93 | "_",
+    Kanji = 0x94 | "HankakuZenkaku",
+    Stop = 0x95,
+    Ax = 0x96,
+    Unlabeled = 0x97,
+    NextTrack = 0x99,
     NumEnter = 0x9C,
-    RightControl = 0x9D,
+    RightControl = 0x9D | "RCtrl" | "RightCtrl",
+    Mute = 0xA0,
+    Calculator = 0xA1,
+    PlayPause = 0xA2,
+    MediaStop = 0xA4,
+    VolumeDown = 0xAE,
+    VolumeUp = 0xB0,
+    WebHome = 0xB2,
+    NumComma = 0xB3,
     NumSlash = 0xB5,
-    PrintScreen = 0xB7,
-    RightAlt = 0xB8,
+    PrintScreen = 0xB7 | "PrtSc" | "SysRq",
+    RightAlt = 0xB8 | "RAlt" | "AltGr",
     Pause = 0xC5,
     Home = 0xC7,
-    UpArrow = 0xC8,
-    PageUp = 0xC9,
-    LeftArrow = 0xCB,
-    RightArrow = 0xCD,
+    UpArrow = 0xC8 | "Up",
+    PageUp = 0xC9 | "PgUp",
+    LeftArrow = 0xCB | "Left",
+    RightArrow = 0xCD | "Right",
     End = 0xCF,
-    DownArrow = 0xD0,
-    PageDown = 0xD1,
-    Insert = 0xD2,
-    Delete = 0xD3,
+    DownArrow = 0xD0 | "Down",
+    PageDown = 0xD1 | "PgDn",
+    Insert = 0xD2 | "Ins",
+    Delete = 0xD3 | "Del",
+    LeftWindows = 0xDB | "LWin" | "Win",
+    RightWindows = 0xDC | "RWin",
+    Apps = 0xDD | "Menu",
+    Power = 0xDE,
+    Sleep = 0xDF,
+    Wake = 0xE3,
+    WebSearch = 0xE5,
+    WebFavorites = 0xE6,
+    WebRefresh = 0xE7,
+    WebStop = 0xE8,
+    WebForward = 0xE9,
+    WebBack = 0xEA,
+    MyComputer = 0xEB,
+    Mail = 0xEC,
+    MediaSelect = 0xED,
 });
 
 input_code!(Mouse {
-    Button1 = 0x00,
-    Button2 = 0x01,
-    // Button3 = 0x02,
-    // Button4 = 0x03,
-    // Button5 = 0x04,
+    Button1 = 0x00 | "LMB" | "LeftClick" | "Mouse1",
+    Button2 = 0x01 | "RMB" | "RightClick" | "Mouse2",
+    Button3 = 0x02 | "MMB" | "MiddleClick" | "Mouse3",
+    Button4 = 0x03 | "Mouse4",
+    Button5 = 0x04 | "Mouse5",
+    Button6 = 0x05 | "Mouse6",
+    Button7 = 0x06 | "Mouse7",
+    Button8 = 0x07 | "Mouse8",
     Move = 0x0a,
-    WheelUp = 0x08,
-    WheelDown = 0x09,
+    WheelUp = 0x08 | "ScrollUp",
+    WheelDown = 0x09 | "ScrollDown",
 });
 
+impl Mouse {
+    /// Whether the game's controls menu lets players remap events to this
+    /// input. The wheel and mouse movement can only be bound by editing
+    /// controlmap.txt.
+    pub fn is_remappable(self) -> bool {
+        !matches!(self, Mouse::Move | Mouse::WheelUp | Mouse::WheelDown)
+    }
+}
+
 input_code!(Gamepad {
-    LeftStick = 0x000b,
-    RightStick = 0x000c,
-    LeftThumb = 0x0040,
-    RightThumb = 0x0080,
-    RightShoulder = 0x0100,
-    LeftShoulder = 0x0200,
+    LeftStick = 0x000b | "LStick",
+    RightStick = 0x000c | "RStick",
+    LeftThumb = 0x0040 | "LThumb",
+    RightThumb = 0x0080 | "RThumb",
+    RightShoulder = 0x0100 | "RB",
+    LeftShoulder = 0x0200 | "LB",
     A = 0x1000,
     B = 0x2000,
     X = 0x4000,
     Y = 0x8000,
-    LeftTrigger = 0x0009,
-    RightTrigger = 0x000a,
-    DpadUp = 0x0001,
-    DpadDown = 0x0002,
-    DpadLeft = 0x0004,
-    DpadRight = 0x0008,
-    Back = 0x0020,
-    Start = 0x0010,
+    LeftTrigger = 0x0009 | "LT",
+    RightTrigger = 0x000a | "RT",
+    DpadUp = 0x0001 | "Up",
+    DpadDown = 0x0002 | "Down",
+    DpadLeft = 0x0004 | "Left",
+    DpadRight = 0x0008 | "Right",
+    Back = 0x0020 | "Select" | "View",
+    Start = 0x0010 | "Menu",
 });
+
+/// A list of codes that are named differently from usual, with their new name
+/// and other names accepted for them when parsing.
+type Renames<T> = &'static [(T, &'static str, &'static [&'static str])];
+
+fn renamed_name<T: InputCode + PartialEq>(renames: Renames<T>, code: T) -> &'static str {
+    renames
+        .iter()
+        .find(|(renamed, _, _)| *renamed == code)
+        .map_or(code.name(), |(_, name, _)| *name)
+}
+
+fn renamed_aliases<T: InputCode + PartialEq>(
+    renames: Renames<T>,
+    code: T,
+) -> &'static [&'static str] {
+    renames
+        .iter()
+        .find(|(renamed, _, _)| *renamed == code)
+        .map_or(code.aliases(), |(_, _, aliases)| *aliases)
+}
+
+/// Looks up a code by its new name, falling back to the usual names for codes
+/// whose usual name isn't taken by another code.
+fn renamed_from_name<T: InputCode>(renames: Renames<T>, name: &str) -> Option<T> {
+    let name = name.trim();
+
+    renames
+        .iter()
+        .find(|(_, canonical, aliases)| {
+            canonical.eq_ignore_ascii_case(name)
+                || aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+        .map(|(code, _, _)| *code)
+        .or_else(|| T::from_name(name))
+}
+
+/// The key labels of different keyboard layouts. Keyboard codes are physical
+/// positions, named after the US QWERTY keys in those positions, and other
+/// layouts rename keys by position, so AZERTY's Z is QWERTY's W.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+}
+
+impl KeyboardLayout {
+    /// The keys this layout labels differently from US QWERTY.
+    fn renamed(self) -> Renames<Keyboard> {
+        match self {
+            KeyboardLayout::Qwerty => &[],
+            KeyboardLayout::Azerty => &[
+                (Keyboard::Minus, "RightParen", &[")"]),
+                (Keyboard::Q, "A", &[]),
+                (Keyboard::W, "Z", &[]),
+                (Keyboard::LeftBracket, "Circumflex", &["^"]),
+                (Keyboard::RightBracket, "Dollar", &["$"]),
+                (Keyboard::A, "Q", &[]),
+                (Keyboard::Semicolon, "M", &[]),
+                (Keyboard::Apostrophe, "UGrave", &["ù"]),
+                (Keyboard::Backtick, "SuperscriptTwo", &["²"]),
+                (Keyboard::BackSlash, "Asterisk", &["*"]),
+                (Keyboard::Z, "W", &[]),
+                (Keyboard::M, "Comma", &[]),
+                (Keyboard::Comma, "Semicolon", &[";"]),
+                (Keyboard::Period, "DeuxPoints", &["Colon", ":"]),
+                (Keyboard::ForwardSlash, "Exclamation", &["!"]),
+                (Keyboard::IntlBackslash, "Less", &["<"]),
+            ],
+            KeyboardLayout::Qwertz => &[
+                (Keyboard::Minus, "SharpS", &["ß"]),
+                (Keyboard::Equals, "Acute", &["´"]),
+                (Keyboard::Y, "Z", &[]),
+                (Keyboard::LeftBracket, "UUmlaut", &["Ü"]),
+                (Keyboard::RightBracket, "Plus", &[]),
+                (Keyboard::Semicolon, "OUmlaut", &["Ö"]),
+                (Keyboard::Apostrophe, "AUmlaut", &["Ä"]),
+                (Keyboard::Backtick, "Circumflex", &["^"]),
+                (Keyboard::BackSlash, "Hash", &["#"]),
+                (Keyboard::Z, "Y", &[]),
+                (Keyboard::ForwardSlash, "Minus", &["-"]),
+                (Keyboard::IntlBackslash, "Less", &["<"]),
+            ],
+            KeyboardLayout::Dvorak => &[
+                (Keyboard::Minus, "LeftBracket", &["["]),
+                (Keyboard::Equals, "RightBracket", &["]"]),
+                (Keyboard::Q, "Apostrophe", &["Quote", "'"]),
+                (Keyboard::W, "Comma", &[]),
+                (Keyboard::E, "Period", &["Dot", "."]),
+                (Keyboard::R, "P", &[]),
+                (Keyboard::T, "Y", &[]),
+                (Keyboard::Y, "F", &[]),
+                (Keyboard::U, "G", &[]),
+                (Keyboard::I, "C", &[]),
+                (Keyboard::O, "R", &[]),
+                (Keyboard::P, "L", &[]),
+                (Keyboard::LeftBracket, "ForwardSlash", &["Slash", "/"]),
+                (Keyboard::RightBracket, "Equals", &["="]),
+                (Keyboard::S, "O", &[]),
+                (Keyboard::D, "E", &[]),
+                (Keyboard::F, "U", &[]),
+                (Keyboard::G, "I", &[]),
+                (Keyboard::H, "D", &[]),
+                (Keyboard::J, "H", &[]),
+                (Keyboard::K, "T", &[]),
+                (Keyboard::L, "N", &[]),
+                (Keyboard::Semicolon, "S", &[]),
+                (Keyboard::Apostrophe, "Minus", &["-"]),
+                (Keyboard::Z, "Semicolon", &[";"]),
+                (Keyboard::X, "Q", &[]),
+                (Keyboard::C, "J", &[]),
+                (Keyboard::V, "K", &[]),
+                (Keyboard::B, "X", &[]),
+                (Keyboard::N, "B", &[]),
+                (Keyboard::Comma, "W", &[]),
+                (Keyboard::Period, "V", &[]),
+                (Keyboard::ForwardSlash, "Z", &[]),
+            ],
+        }
+    }
+
+    /// The label of a key in this layout.
+    pub fn name(self, key: Keyboard) -> &'static str {
+        renamed_name(self.renamed(), key)
+    }
+
+    /// Other names accepted for a key in this layout when parsing.
+    pub fn aliases(self, key: Keyboard) -> &'static [&'static str] {
+        renamed_aliases(self.renamed(), key)
+    }
+
+    /// Looks up a key by its label in this layout, ignoring case. QWERTY
+    /// names are accepted too, unless this layout uses the same label for
+    /// another key.
+    pub fn key(self, name: &str) -> Option<Keyboard> {
+        renamed_from_name(self.renamed(), name)
+    }
+}
+
+impl FromStr for KeyboardLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "qwerty" => Ok(KeyboardLayout::Qwerty),
+            "azerty" => Ok(KeyboardLayout::Azerty),
+            "qwertz" => Ok(KeyboardLayout::Qwertz),
+            "dvorak" => Ok(KeyboardLayout::Dvorak),
+            _ => bail!(
+                "Unknown keyboard layout {}, expected qwerty, azerty, qwertz, or dvorak",
+                s
+            ),
+        }
+    }
+}
+
+/// The button names printed on different brands of controller. The game
+/// always uses Xbox names, and other styles rename buttons by their position
+/// on the controller, so Cross and Nintendo's B are both Xbox's A.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PadStyle {
+    #[default]
+    Xbox,
+    PlayStation,
+    Nintendo,
+}
+
+impl PadStyle {
+    /// The buttons this style names differently from Xbox.
+    fn renamed(self) -> Renames<Gamepad> {
+        match self {
+            PadStyle::Xbox => &[],
+            PadStyle::PlayStation => &[
+                (Gamepad::A, "Cross", &[]),
+                (Gamepad::B, "Circle", &[]),
+                (Gamepad::X, "Square", &[]),
+                (Gamepad::Y, "Triangle", &[]),
+                (Gamepad::LeftShoulder, "L1", &[]),
+                (Gamepad::RightShoulder, "R1", &[]),
+                (Gamepad::LeftTrigger, "L2", &[]),
+                (Gamepad::RightTrigger, "R2", &[]),
+                (Gamepad::LeftThumb, "L3", &[]),
+                (Gamepad::RightThumb, "R3", &[]),
+                (Gamepad::Back, "Share", &["Create"]),
+                (Gamepad::Start, "Options", &[]),
+            ],
+            PadStyle::Nintendo => &[
+                (Gamepad::A, "B", &[]),
+                (Gamepad::B, "A", &[]),
+                (Gamepad::X, "Y", &[]),
+                (Gamepad::Y, "X", &[]),
+                (Gamepad::LeftShoulder, "L", &[]),
+                (Gamepad::RightShoulder, "R", &[]),
+                (Gamepad::LeftTrigger, "ZL", &[]),
+                (Gamepad::RightTrigger, "ZR", &[]),
+                (Gamepad::LeftThumb, "LeftStickPress", &["LSPress"]),
+                (Gamepad::RightThumb, "RightStickPress", &["RSPress"]),
+                (Gamepad::Back, "Minus", &[]),
+                (Gamepad::Start, "Plus", &[]),
+            ],
+        }
+    }
+
+    /// The name of a button in this style.
+    pub fn name(self, button: Gamepad) -> &'static str {
+        renamed_name(self.renamed(), button)
+    }
+
+    /// Other names accepted for a button in this style when parsing.
+    pub fn aliases(self, button: Gamepad) -> &'static [&'static str] {
+        renamed_aliases(self.renamed(), button)
+    }
+
+    /// Looks up a button by its name in this style, ignoring case. Xbox names
+    /// are accepted too, unless this style uses the same name for another
+    /// button.
+    pub fn button(self, name: &str) -> Option<Gamepad> {
+        renamed_from_name(self.renamed(), name)
+    }
+}
+
+impl FromStr for PadStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "xbox" => Ok(PadStyle::Xbox),
+            "playstation" | "ps" => Ok(PadStyle::PlayStation),
+            "nintendo" | "switch" => Ok(PadStyle::Nintendo),
+            _ => bail!(
+                "Unknown pad style {}, expected xbox, playstation, or nintendo",
+                s
+            ),
+        }
+    }
+}
+
+// How to name keyboard keys and gamepad buttons when printing or parsing
+// bindings for people. Files always use input codes, so this never changes
+// what a binding means. This isn't a doc comment because structopt would use
+// it as the about text of every subcommand that flattens it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, StructOpt)]
+pub struct InputNames {
+    /// The keyboard layout to name keys by: qwerty, azerty, qwertz, or
+    /// dvorak.
+    #[structopt(long, default_value = "qwerty")]
+    pub layout: KeyboardLayout,
+
+    /// How to name gamepad buttons: xbox, playstation, or nintendo.
+    #[structopt(long, default_value = "xbox")]
+    pub pad_style: PadStyle,
+}
@@ -1,6 +1,10 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+
 macro_rules! contexts {
 	($( $name:ident = $code:literal, )*) => {
-		#[derive(Debug)]
+		#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 		pub enum InputContext {
 			$( $name = $code, )*
 		}
@@ -12,6 +16,16 @@ macro_rules! contexts {
 					_ => None
 				}
 			}
+
+			pub fn all() -> &'static [Self] {
+				&[ $( Self::$name, )* ]
+			}
+
+			pub fn name(&self) -> &'static str {
+				match self {
+					$( Self::$name => stringify!($name), )*
+				}
+			}
 		}
 	}
 }
@@ -35,3 +49,60 @@ contexts! {
     Lockpicking = 15,
     Favor = 16,
 }
+
+impl InputContext {
+    /// Finds a context by name, ignoring case, spaces, and punctuation so that
+    /// `MainGameplay`, `main gameplay` and `Main-Gameplay` are all accepted.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let wanted = normalize_name(name);
+
+        Self::all()
+            .iter()
+            .copied()
+            .find(|context| normalize_name(context.name()) == wanted)
+    }
+}
+
+impl InputContext {
+    /// Tells whether this context is only used by the game's debug tools,
+    /// rather than by normal play.
+    pub fn is_debug(self) -> bool {
+        matches!(
+            self,
+            InputContext::DebugText
+                | InputContext::DebugOverlay
+                | InputContext::TfcMode
+                | InputContext::DebugMap
+        )
+    }
+}
+
+/// Names a section of a controlmap file by its input context.
+pub fn section_name(section: usize) -> String {
+    match InputContext::from_u32(section as u32) {
+        Some(context) => context.name().to_owned(),
+        None => format!("<unknown section {}>", section),
+    }
+}
+
+/// Parses either a context name or its numeric ID.
+impl FromStr for InputContext {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Ok(id) = s.parse::<u32>() {
+            return Self::from_u32(id).with_context(|| format!("Unknown input context ID {}", id));
+        }
+
+        Self::from_name(s).with_context(|| format!("Unknown input context {}", s))
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
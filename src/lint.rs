@@ -0,0 +1,220 @@
+//! Checks for mistakes in controlmap files that the game won't report.
+
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+
+use crate::format::{ControlMapFile, Device, Input};
+use crate::input_context::{section_name, InputContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    /// The section the problem is in, if it's tied to one.
+    pub section: Option<usize>,
+
+    /// The line the problem is on, if it's tied to one.
+    pub line: Option<usize>,
+
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.severity)?;
+
+        if let Some(line) = self.line {
+            write!(f, ": line {}", line)?;
+        }
+
+        if let Some(section) = self.section {
+            write!(f, " ({})", section_name(section))?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl ControlMapFile {
+    /// Checks this file for broken aliases, unknown input codes and event
+    /// flags, structural problems, and vanilla events that have gone missing
+    /// compared to `vanilla`.
+    pub fn lint(&self, vanilla: &ControlMapFile) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let expected_sections = InputContext::all().len();
+        if self.sections.len() != expected_sections {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                section: None,
+                line: None,
+                message: format!(
+                    "file has {} sections, but the game expects {}",
+                    self.sections.len(),
+                    expected_sections
+                ),
+            });
+        }
+
+        for (section_id, section) in self.sections.iter().enumerate() {
+            for entry in section.entries() {
+                let mut report = |severity, message| {
+                    diagnostics.push(Diagnostic {
+                        severity,
+                        section: Some(section_id),
+                        line: entry.line,
+                        message,
+                    })
+                };
+
+                if let Some(flag) = entry.event_flag {
+                    if flag.unknown_bits() != 0 {
+                        report(
+                            Severity::Warning,
+                            format!(
+                                "{}: event flag {:#x} sets unknown bits {:#x}",
+                                entry.event,
+                                flag,
+                                flag.unknown_bits()
+                            ),
+                        );
+                    }
+                }
+
+                for device in Device::all() {
+                    let binding = entry.binding(device);
+
+                    for resolved in self.trace_binding(binding, device) {
+                        if let Err(err) = resolved {
+                            report(
+                                Severity::Error,
+                                format!("{} ({}): {}", entry.event, device, err),
+                            );
+                        }
+                    }
+
+                    for input in &binding.inputs {
+                        if let Input::Inputs(codes) = input {
+                            for &code in codes {
+                                if device.code_name(code).is_none() {
+                                    report(
+                                        Severity::Warning,
+                                        format!(
+                                            "{} ({}): unknown input code {:#04x}",
+                                            entry.event, device, code
+                                        ),
+                                    );
+                                }
+                            }
+                        }
+                    }
+
+                    if entry.mappable(device) {
+                        for code in device.unremappable_codes(binding) {
+                            report(
+                                Severity::Warning,
+                                format!(
+                                    "{} is marked as remappable on {}, but is bound to {}, which can't be remapped to in-game",
+                                    entry.event,
+                                    device,
+                                    device.code_name(code).unwrap_or_default()
+                                ),
+                            );
+                        }
+                    }
+
+                    let alias_only = !binding.inputs.is_empty()
+                        && binding
+                            .inputs
+                            .iter()
+                            .all(|input| matches!(input, Input::Alias { .. }));
+
+                    if alias_only && entry.mappable(device) {
+                        report(
+                            Severity::Warning,
+                            format!(
+                                "{} is marked as remappable on {}, but is only bound through aliases",
+                                entry.event, device
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        for (section_id, vanilla_section) in vanilla.sections.iter().enumerate() {
+            let present: HashSet<_> = match self.sections.get(section_id) {
+                Some(section) => section.entries().map(|entry| &entry.event).collect(),
+                None => HashSet::new(),
+            };
+
+            let mut reported = HashSet::new();
+            for entry in vanilla_section.entries() {
+                if !present.contains(&entry.event) && reported.insert(&entry.event) {
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Warning,
+                        section: Some(section_id),
+                        line: None,
+                        message: format!("vanilla event {} is missing", entry.event),
+                    });
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_CONTROLMAP;
+
+    #[test]
+    fn vanilla_has_no_errors() {
+        let vanilla: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+
+        let errors: Vec<_> = vanilla
+            .lint(&vanilla)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_broken_aliases() {
+        let vanilla: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+        let mut map = vanilla.clone();
+        map.find_entry_mut(InputContext::Menu as usize, "Accept")
+            .unwrap()
+            .gamepad = "!0,Fly".parse().unwrap();
+
+        let errors: Vec<_> = map
+            .lint(&vanilla)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .collect();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].section, Some(InputContext::Menu as usize));
+        assert!(errors[0].message.starts_with("Accept (gamepad): "));
+    }
+}
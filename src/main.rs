@@ -1,17 +1,41 @@
+mod aliases;
+mod cheatsheet;
 mod columnar;
+mod conflicts;
+mod dedupe;
+mod diff;
+mod event_flags;
+mod export;
 mod format;
 mod input_codes;
 mod input_context;
+mod lint;
+mod merge;
+mod patch;
+mod relayout;
+mod render;
+mod source;
 
-use std::collections::{HashMap, VecDeque};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context};
 use structopt::StructOpt;
 
-use crate::format::{ControlMapFile, ControlMapLine, PrettyPrintBinding};
-use crate::input_codes::{Gamepad, Keyboard, Mouse};
-use crate::input_context::InputContext;
+use crate::cheatsheet::CheatSheetOptions;
+use crate::dedupe::DedupePolicy;
+use crate::diff::EventDisplay;
+use crate::export::ExportFormat;
+use crate::format::{Binding, ControlMapFile, ControlMapLine, Device, Input};
+use crate::input_codes::{InputCode, InputNames, Keyboard, KeyboardLayout};
+use crate::input_context::{section_name, InputContext};
+use crate::lint::Severity;
+use crate::merge::{
+    merge_overwrite, merge_three_way, Blame, BlameFormat, ConflictStrategy, FieldGroup,
+    MergeFields, MergeInput,
+};
+use crate::patch::{Patch, PatchField};
+use crate::relayout::KeyMoveOutcome;
+use crate::source::SourceFormat;
 
 static DEFAULT_CONTROLMAP: &str = include_str!("../maps/controlmap-default.txt");
 
@@ -37,6 +61,13 @@ pub enum Subcommand {
 
         /// Output path. Will overwrite the input path if not given.
         output: Option<PathBuf>,
+
+        /// What to do with events defined more than once in a section:
+        /// remove, merge, keep-known, or report. remove keeps only the last
+        /// entry for each event, and keep-known also keeps the duplicates
+        /// vanilla relies on, like RotateLock.
+        #[structopt(long, default_value = "keep-known")]
+        dedupe: DedupePolicy,
     },
 
     /// Print a controlmap file with human-readable descriptions of what all of
@@ -44,10 +75,18 @@ pub enum Subcommand {
     Explain {
         /// The file to explain.
         input: PathBuf,
+
+        #[structopt(flatten)]
+        names: InputNames,
     },
 
     /// Merge multiple controlmap files together, letting later files overwrite
-    /// matching entries in earlier files.
+    /// matching entries in earlier files. Events and sections that only later
+    /// files have are added, along with the comments above them.
+    ///
+    /// With --base, does a three-way merge instead: every input is compared
+    /// against the common base, and changes to different fields of the same
+    /// event are combined.
     Merge {
         /// The files to merge together, in order.
         inputs: Vec<PathBuf>,
@@ -55,6 +94,331 @@ pub enum Subcommand {
         /// Where to output the merged result.
         #[structopt(long, short)]
         output: PathBuf,
+
+        /// The file all of the inputs were derived from. Enables three-way
+        /// merging.
+        #[structopt(long)]
+        base: Option<PathBuf>,
+
+        /// What to do when inputs change the same field differently during a
+        /// three-way merge: fail, prefer-left, prefer-right, or markers.
+        #[structopt(long, default_value = "fail")]
+        on_conflict: ConflictStrategy,
+
+        /// Only take bindings and mappable flags for these devices from later
+        /// files, like `gamepad` or `keyboard,mouse`. Defaults to all devices.
+        #[structopt(long, use_delimiter = true)]
+        devices: Vec<Device>,
+
+        /// Only take these fields from later files: bindings, mappable, or
+        /// flags, like `bindings,mappable`. Defaults to all fields.
+        #[structopt(long, use_delimiter = true)]
+        fields: Vec<FieldGroup>,
+
+        /// Print which input each binding in the result came from.
+        #[structopt(long)]
+        blame: bool,
+
+        /// How to print blame information: table or json.
+        #[structopt(long, default_value = "table")]
+        blame_format: BlameFormat,
+    },
+
+    /// Show which input each binding would come from if the given files were
+    /// merged, without writing the merged result.
+    Blame {
+        /// The files to merge together, in order.
+        inputs: Vec<PathBuf>,
+
+        /// The file all of the inputs were derived from. Enables three-way
+        /// merging.
+        #[structopt(long)]
+        base: Option<PathBuf>,
+
+        /// What to do when inputs change the same field differently during a
+        /// three-way merge: fail, prefer-left, prefer-right, or markers.
+        #[structopt(long, default_value = "fail")]
+        on_conflict: ConflictStrategy,
+
+        /// Only take bindings and mappable flags for these devices from later
+        /// files, like `gamepad` or `keyboard,mouse`. Defaults to all devices.
+        #[structopt(long, use_delimiter = true)]
+        devices: Vec<Device>,
+
+        /// Only take these fields from later files: bindings, mappable, or
+        /// flags, like `bindings,mappable`. Defaults to all fields.
+        #[structopt(long, use_delimiter = true)]
+        fields: Vec<FieldGroup>,
+
+        /// How to print blame information: table or json.
+        #[structopt(long, default_value = "table")]
+        format: BlameFormat,
+    },
+
+    /// Change what an event is bound to on one device, using key names
+    /// instead of raw input codes.
+    Bind {
+        /// The controlmap file to edit.
+        input: PathBuf,
+
+        /// The input context containing the event, by name (like
+        /// `MainGameplay`) or by number.
+        context: InputContext,
+
+        /// The name of the event to rebind, like `Jump`.
+        event: String,
+
+        /// The device to rebind: keyboard, mouse, or gamepad.
+        device: Device,
+
+        /// The new binding. Use `+` to combine keys and `,` to separate
+        /// alternatives, like `LeftControl+E` or `A,RightShoulder`.
+        keys: String,
+
+        #[structopt(flatten)]
+        names: InputNames,
+
+        /// Output path. Will overwrite the input path if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Remove bindings from an event on one device. Clears the device's
+    /// binding entirely unless specific keys are given.
+    Unbind {
+        /// The controlmap file to edit.
+        input: PathBuf,
+
+        /// The input context containing the event, by name or by number.
+        context: InputContext,
+
+        /// The name of the event to unbind, like `Hotkey1`.
+        event: String,
+
+        /// The device to unbind: keyboard, mouse, or gamepad.
+        device: Device,
+
+        /// Only remove these alternatives, written the same way as for `bind`.
+        /// Other alternatives, including aliases, are kept.
+        keys: Option<String>,
+
+        #[structopt(flatten)]
+        names: InputNames,
+
+        /// Output path. Will overwrite the input path if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Set the user event flags of an event, which group it with related
+    /// events so the game can turn them on and off together.
+    Flag {
+        /// The controlmap file to edit.
+        input: PathBuf,
+
+        /// The input context containing the event, by name or by number.
+        context: InputContext,
+
+        /// The name of the event, like `Sprint`.
+        event: String,
+
+        /// The new flags, by name or in hex, like `Movement|VATS` or `0x801`.
+        /// `none` removes the event flag column.
+        flags: String,
+
+        /// Output path. Will overwrite the input path if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Swap what two events in the same input context are bound to on one
+    /// device. Entries in any context that alias either event follow it to
+    /// its new inputs, unless --keep-aliases is given.
+    Swap {
+        /// The controlmap file to edit.
+        input: PathBuf,
+
+        /// The input context containing both events, by name or by number.
+        context: InputContext,
+
+        /// The first event, like `Jump`.
+        first: String,
+
+        /// The second event, like `Ready Weapon`.
+        second: String,
+
+        /// The device to swap: keyboard, mouse, or gamepad.
+        device: Device,
+
+        /// Keep entries that alias either event on the physical inputs they
+        /// had before the swap, by pointing their aliases at the other event.
+        #[structopt(long)]
+        keep_aliases: bool,
+
+        /// Output path. Will overwrite the input path if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Move keyboard bindings written for one keyboard layout to another, so
+    /// every key keeps its label. Movement keys keep their physical position
+    /// instead. Keys whose new position is already taken are left alone, and
+    /// nothing is written if the result would still have new conflicts.
+    Relayout {
+        /// The controlmap file to edit.
+        input: PathBuf,
+
+        /// The layout the bindings were written for: qwerty, azerty, qwertz,
+        /// or dvorak.
+        #[structopt(long, default_value = "qwerty")]
+        from: KeyboardLayout,
+
+        /// The layout to move the bindings to.
+        #[structopt(long)]
+        to: KeyboardLayout,
+
+        /// Keys that stay in their physical position, named in the --from
+        /// layout and separated by commas. Defaults to the keys where WASD is
+        /// on a QWERTY keyboard.
+        #[structopt(long, use_delimiter = true)]
+        positional: Vec<String>,
+
+        /// Output path. Will overwrite the input path if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Report inputs that are bound to more than one event in the same input
+    /// context.
+    Conflicts {
+        /// The file to check.
+        input: PathBuf,
+    },
+
+    /// Check a controlmap file for broken aliases, unknown input codes and
+    /// event flags, structural problems, and missing vanilla events. Exits
+    /// with an error if any errors are found.
+    Lint {
+        /// The file to check.
+        input: PathBuf,
+    },
+
+    /// Show what changed between two controlmap files, event by event, using
+    /// key names.
+    Diff {
+        /// The original file.
+        old: PathBuf,
+
+        /// The changed file.
+        new: PathBuf,
+    },
+
+    /// Apply patch files to a controlmap. Patches set individual fields of
+    /// events, like `Jump.gamepad = Y` under a `[Main Gameplay]` header.
+    Apply {
+        /// The controlmap file to patch.
+        input: PathBuf,
+
+        /// The patch files to apply, in order.
+        #[structopt(required = true)]
+        patches: Vec<PathBuf>,
+
+        /// Fail instead of skipping changes to events that don't exist.
+        #[structopt(long)]
+        strict: bool,
+
+        /// Output path. Will overwrite the input path if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Generate a patch containing only the fields that differ between a base
+    /// controlmap and a modified one.
+    MakePatch {
+        /// The modified controlmap file.
+        modified: PathBuf,
+
+        /// The controlmap the changes were made to. Defaults to the vanilla
+        /// controlmap.
+        #[structopt(long, short)]
+        base: Option<PathBuf>,
+
+        /// Where to write the patch. Prints it if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Export a controlmap file in another format: JSON for use by other
+    /// tools, or a Markdown or HTML cheat sheet listing every binding.
+    Export {
+        /// The file to export.
+        input: PathBuf,
+
+        /// The format to export to: json, markdown, or html.
+        #[structopt(long, default_value = "json")]
+        format: ExportFormat,
+
+        /// Leave events that aren't bound to anything out of cheat sheets.
+        #[structopt(long)]
+        hide_unbound: bool,
+
+        /// Leave input contexts only used by debug tools out of cheat sheets.
+        #[structopt(long)]
+        hide_debug: bool,
+
+        #[structopt(flatten)]
+        names: InputNames,
+
+        /// Where to write the export. Prints it if not given.
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Convert a JSON export back into a controlmap file.
+    Import {
+        /// The JSON file to import.
+        input: PathBuf,
+
+        /// Where to write the controlmap.
+        #[structopt(long, short)]
+        output: PathBuf,
+    },
+
+    /// Compile a controlmap written by hand in TOML or YAML, using key names
+    /// and input context names, into a controlmap.txt file.
+    Build {
+        /// The TOML or YAML file to compile.
+        input: PathBuf,
+
+        /// Where to write the controlmap.
+        #[structopt(long, short)]
+        output: PathBuf,
+
+        /// The format of the input: toml or yaml. Guessed from the file
+        /// extension if not given.
+        #[structopt(long)]
+        format: Option<SourceFormat>,
+    },
+
+    /// Draw a diagram of a gamepad, keyboard, or mouse as SVG, with every
+    /// button labeled by the events bound to it in one input context.
+    Render {
+        /// The controlmap file to draw.
+        input: PathBuf,
+
+        /// Where to write the SVG.
+        output: PathBuf,
+
+        /// The device to draw: keyboard, mouse, or gamepad.
+        #[structopt(long, default_value = "gamepad")]
+        device: Device,
+
+        /// The input context to show bindings for, by name or by number.
+        #[structopt(long, default_value = "MainGameplay")]
+        context: InputContext,
+
+        #[structopt(flatten)]
+        names: InputNames,
     },
 }
 
@@ -66,17 +430,46 @@ fn run() -> anyhow::Result<()> {
             fs_err::write(output, DEFAULT_CONTROLMAP)?;
         }
 
-        Subcommand::Reformat { input, output } => {
+        Subcommand::Reformat {
+            input,
+            output,
+            dedupe,
+        } => {
             let contents = fs_err::read_to_string(&input)?;
             let mut map: ControlMapFile = contents.parse()?;
-            map.remove_duplicates();
+
+            for duplicate in map.dedupe(dedupe) {
+                if duplicate.intentional && dedupe == DedupePolicy::KeepKnown {
+                    continue;
+                }
+
+                let lines: Vec<String> = duplicate
+                    .lines
+                    .iter()
+                    .map(|line| line.map_or_else(|| "?".to_owned(), |line| line.to_string()))
+                    .collect();
+                let action = match dedupe {
+                    DedupePolicy::Remove | DedupePolicy::KeepKnown => "keeping the last",
+                    DedupePolicy::Merge => "merging them",
+                    DedupePolicy::Report => "leaving them alone",
+                };
+
+                eprintln!(
+                    "{}: {} is defined on lines {}, {}",
+                    section_name(duplicate.section),
+                    duplicate.event,
+                    lines.join(", "),
+                    action
+                );
+            }
+
             let formatted = map.to_string();
 
             let output_path = output.as_ref().unwrap_or(&input);
             fs_err::write(output_path, formatted)?;
         }
 
-        Subcommand::Explain { input } => {
+        Subcommand::Explain { input, names } => {
             let contents = fs_err::read_to_string(&input)?;
             let map: ControlMapFile = contents.parse()?;
 
@@ -92,77 +485,592 @@ fn run() -> anyhow::Result<()> {
                         println!("{} is bound to:", entry.event);
                         println!(
                             "    Keyboard: {}",
-                            PrettyPrintBinding::<Keyboard>::new(&entry.keyboard)
+                            map.explain_binding(&entry.keyboard, Device::Keyboard, names)
                         );
                         println!(
                             "    Mouse:    {}",
-                            PrettyPrintBinding::<Mouse>::new(&entry.mouse)
+                            map.explain_binding(&entry.mouse, Device::Mouse, names)
                         );
                         println!(
                             "    Gamepad:  {}",
-                            PrettyPrintBinding::<Gamepad>::new(&entry.gamepad)
+                            map.explain_binding(&entry.gamepad, Device::Gamepad, names)
                         );
+                        if let Some(flag) = entry.event_flag {
+                            println!("    Flags:    {}", flag);
+                        }
                         println!();
                     }
                 }
             }
         }
 
-        Subcommand::Merge { inputs, output } => {
-            if inputs.is_empty() {
-                bail!("No input files specified");
+        Subcommand::Merge {
+            inputs,
+            output,
+            base,
+            on_conflict,
+            devices,
+            fields,
+            blame,
+            blame_format,
+        } => {
+            let fields = MergeFields::new(devices, fields);
+            let (merged, blame_info) = merge_files(&inputs, base.as_deref(), on_conflict, &fields)?;
+
+            fs_err::write(output, merged.to_string_lossless())?;
+
+            if blame {
+                print_blame(&blame_info, &merged, blame_format)?;
             }
+        }
 
-            let mut maps = VecDeque::new();
-            for input in inputs {
-                let contents = fs_err::read_to_string(&input)?;
-                let map: ControlMapFile = contents
-                    .parse()
-                    .with_context(|| format!("Could not parse {}", input.display()))?;
+        Subcommand::Blame {
+            inputs,
+            base,
+            on_conflict,
+            devices,
+            fields,
+            format,
+        } => {
+            let fields = MergeFields::new(devices, fields);
+            let (merged, blame) = merge_files(&inputs, base.as_deref(), on_conflict, &fields)?;
+            print_blame(&blame, &merged, format)?;
+        }
 
-                maps.push_back(map);
+        Subcommand::Bind {
+            input,
+            context,
+            event,
+            device,
+            keys,
+            names,
+            output,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let mut map: ControlMapFile = contents.parse()?;
+
+            let binding = device.parse_binding(&keys, names)?;
+            warn_unremappable(&event, device, &binding);
+            let entry = map
+                .find_entry_mut(context as usize, &event)
+                .with_context(|| format!("No event named {} in {:?}", event, context))?;
+            *entry.binding_mut(device) = binding;
+
+            let output_path = output.as_ref().unwrap_or(&input);
+            fs_err::write(output_path, map.to_string_lossless())?;
+        }
+
+        Subcommand::Unbind {
+            input,
+            context,
+            event,
+            device,
+            keys,
+            names,
+            output,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let mut map: ControlMapFile = contents.parse()?;
+
+            let filter = keys
+                .as_deref()
+                .map(|keys| device.parse_binding(keys, names))
+                .transpose()?;
+            let entry = map
+                .find_entry_mut(context as usize, &event)
+                .with_context(|| format!("No event named {} in {:?}", event, context))?;
+            let binding = entry.binding_mut(device);
+
+            match filter {
+                Some(filter) => {
+                    if binding.remove_matching(&filter) == 0 {
+                        bail!(
+                            "{} is not bound to {} on {:?}",
+                            event,
+                            keys.unwrap(),
+                            device
+                        );
+                    }
+                }
+                None => binding.inputs.clear(),
+            }
+
+            let output_path = output.as_ref().unwrap_or(&input);
+            fs_err::write(output_path, map.to_string_lossless())?;
+        }
+
+        Subcommand::Flag {
+            input,
+            context,
+            event,
+            flags,
+            output,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let mut map: ControlMapFile = contents.parse()?;
+
+            let flags = if flags.eq_ignore_ascii_case("none") {
+                None
+            } else {
+                Some(flags.parse()?)
+            };
+            let entry = map
+                .find_entry_mut(context as usize, &event)
+                .with_context(|| format!("No event named {} in {:?}", event, context))?;
+            entry.event_flag = flags;
+
+            let output_path = output.as_ref().unwrap_or(&input);
+            fs_err::write(output_path, map.to_string_lossless())?;
+        }
+
+        Subcommand::Swap {
+            input,
+            context,
+            first,
+            second,
+            device,
+            keep_aliases,
+            output,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let mut map: ControlMapFile = contents.parse()?;
+
+            map.swap_bindings(context as usize, &first, &second, device)?;
+
+            if keep_aliases {
+                let kept = map.swap_aliases(context as usize, &first, &second, device);
+                println!("Kept {} aliases on their old inputs", kept);
             }
 
-            let mut base_map = maps.pop_front().unwrap();
-            let mut patches = Vec::new();
+            let output_path = output.as_ref().unwrap_or(&input);
+            fs_err::write(output_path, map.to_string_lossless())?;
+        }
+
+        Subcommand::Relayout {
+            input,
+            from,
+            to,
+            positional,
+            output,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let mut map: ControlMapFile = contents.parse()?;
+
+            let positional = if positional.is_empty() {
+                vec![Keyboard::W, Keyboard::A, Keyboard::S, Keyboard::D]
+            } else {
+                positional
+                    .iter()
+                    .map(|name| {
+                        from.key(name)
+                            .with_context(|| format!("Unknown key {} on {:?}", name, from))
+                    })
+                    .collect::<anyhow::Result<_>>()?
+            };
+
+            let old_conflicts = map.conflicts();
+            for key_move in map.relayout(from, to, &positional) {
+                // Labels are the same before and after a move, so keys are
+                // reported by their physical position, named as on QWERTY.
+                let context = section_name(key_move.section);
+                let label = from.name(key_move.from);
 
-            while let Some(map) = maps.pop_front() {
-                for _ in map.sections.len()..patches.len() {
-                    patches.push(HashMap::new());
+                match key_move.outcome {
+                    KeyMoveOutcome::Moved(key) => println!(
+                        "{}: {} stays on {}, moving from the QWERTY {} key to the QWERTY {} key ({:#04x} -> {:#04x})",
+                        context,
+                        key_move.event,
+                        label,
+                        key_move.from.name(),
+                        key.name(),
+                        key_move.from.to_u32(),
+                        key.to_u32()
+                    ),
+                    KeyMoveOutcome::Missing => eprintln!(
+                        "warning: {}: {} is bound to {} (the QWERTY {} key), which {:?} doesn't have, so it was left alone",
+                        context,
+                        key_move.event,
+                        label,
+                        key_move.from.name(),
+                        to
+                    ),
+                    KeyMoveOutcome::Taken(key) => eprintln!(
+                        "warning: {}: {} is bound to {}, but {} on {:?} is the QWERTY {} key, which is already taken there, so it was left alone",
+                        context,
+                        key_move.event,
+                        label,
+                        label,
+                        to,
+                        key.name()
+                    ),
                 }
+            }
 
-                for (section_id, section) in map.sections.into_iter().enumerate() {
-                    let section_patches = &mut patches[section_id];
+            // Aliases can still carry a moved key into another context, so
+            // anything that now collides is reported and nothing is written.
+            let mut new_conflicts = Vec::new();
+            for conflict in map.conflicts() {
+                let existed = old_conflicts.iter().any(|old| {
+                    old.section == conflict.section
+                        && old.device == conflict.device
+                        && old.chord == conflict.chord
+                });
+                if existed || conflict.device != Device::Keyboard {
+                    continue;
+                }
 
-                    for line in section.body {
-                        if let ControlMapLine::Entry(entry) = line {
-                            section_patches.insert(entry.event.clone(), entry);
+                let chord = Binding {
+                    inputs: vec![Input::Inputs(conflict.chord)],
+                };
+                let events: Vec<String> = conflict
+                    .events
+                    .into_iter()
+                    .map(|(event, _)| event)
+                    .collect();
+                new_conflicts.push(format!(
+                    "{}: {} would be bound to {}",
+                    section_name(conflict.section),
+                    Device::Keyboard.describe(
+                        &chord,
+                        InputNames {
+                            layout: to,
+                            ..InputNames::default()
                         }
+                    ),
+                    events.join(", ")
+                ));
+            }
+
+            if !new_conflicts.is_empty() {
+                for conflict in &new_conflicts {
+                    eprintln!("{}", conflict);
+                }
+                bail!(
+                    "Moving to {:?} would bind more than one event to the same key, so {} was left unchanged",
+                    to,
+                    input.display()
+                );
+            }
+
+            let output_path = output.as_ref().unwrap_or(&input);
+            fs_err::write(output_path, map.to_string_lossless())?;
+        }
+
+        Subcommand::Conflicts { input } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let map: ControlMapFile = contents.parse()?;
+
+            let conflicts = map.conflicts();
+            if conflicts.is_empty() {
+                println!("No conflicts found.");
+            }
+
+            for conflict in conflicts {
+                let chord = Binding {
+                    inputs: vec![Input::Inputs(conflict.chord)],
+                };
+
+                println!(
+                    "{}: {} {} is bound to:",
+                    section_name(conflict.section),
+                    conflict.device,
+                    conflict.device.describe(&chord, InputNames::default())
+                );
+
+                for (event, line) in conflict.events {
+                    match line {
+                        Some(line) => println!("    {} (line {})", event, line),
+                        None => println!("    {}", event),
                     }
                 }
+
+                println!();
             }
+        }
 
-            for (section_id, section) in base_map.sections.iter_mut().enumerate() {
-                let section_patches = &mut patches[section_id];
+        Subcommand::Lint { input } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let map: ControlMapFile = contents.parse()?;
+            let vanilla: ControlMapFile = DEFAULT_CONTROLMAP.parse()?;
 
-                for line in &mut section.body {
-                    if let ControlMapLine::Entry(entry) = line {
-                        if let Some(patch) = section_patches.remove(&entry.event) {
-                            *entry = patch;
-                        }
+            let diagnostics = map.lint(&vanilla);
+            for diagnostic in &diagnostics {
+                println!("{}: {}", input.display(), diagnostic);
+            }
+
+            let errors = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .count();
+            let warnings = diagnostics.len() - errors;
+            println!("{} errors, {} warnings", errors, warnings);
+
+            if errors > 0 {
+                bail!("{} failed lint checks", input.display());
+            }
+        }
+
+        Subcommand::Diff { old, new } => {
+            let old_map: ControlMapFile = fs_err::read_to_string(&old)?
+                .parse()
+                .with_context(|| format!("Could not parse {}", old.display()))?;
+            let new_map: ControlMapFile = fs_err::read_to_string(&new)?
+                .parse()
+                .with_context(|| format!("Could not parse {}", new.display()))?;
+
+            let changes = old_map.diff(&new_map);
+            if changes.is_empty() {
+                println!("No differences found.");
+            }
+
+            let mut current_section = None;
+            for change in changes {
+                if current_section != Some(change.section) {
+                    if current_section.is_some() {
+                        println!();
                     }
+
+                    println!("==== {} ====", section_name(change.section));
+                    current_section = Some(change.section);
                 }
+
+                println!("{}", change.kind);
             }
+        }
+
+        Subcommand::Apply {
+            input,
+            patches,
+            strict,
+            output,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let mut map: ControlMapFile = contents.parse()?;
+
+            let mut num_missing = 0;
+            for patch_path in &patches {
+                let patch: Patch = fs_err::read_to_string(patch_path)?
+                    .parse()
+                    .with_context(|| format!("Could not parse {}", patch_path.display()))?;
+
+                for change in &patch.changes {
+                    if let PatchField::Binding(device, binding) = &change.field {
+                        warn_unremappable(&change.event, *device, binding);
+                    }
+                }
+
+                for change in map.apply_patch(&patch) {
+                    eprintln!(
+                        "{}:{}: no event named {} in {}",
+                        patch_path.display(),
+                        change.line.unwrap_or(0),
+                        change.event,
+                        section_name(change.context)
+                    );
+                    num_missing += 1;
+                }
+            }
+
+            if strict && num_missing > 0 {
+                bail!("{} changes refer to events that don't exist", num_missing);
+            }
+
+            let output_path = output.as_ref().unwrap_or(&input);
+            fs_err::write(output_path, map.to_string_lossless())?;
+        }
+
+        Subcommand::MakePatch {
+            modified,
+            base,
+            output,
+        } => {
+            let base_map: ControlMapFile = match &base {
+                Some(base) => fs_err::read_to_string(base)?
+                    .parse()
+                    .with_context(|| format!("Could not parse {}", base.display()))?,
+                None => DEFAULT_CONTROLMAP.parse()?,
+            };
+            let modified_map: ControlMapFile = fs_err::read_to_string(&modified)?
+                .parse()
+                .with_context(|| format!("Could not parse {}", modified.display()))?;
+
+            let (patch, unsupported) = base_map.make_patch(&modified_map);
+            for change in unsupported {
+                eprintln!(
+                    "Skipping change that patches can't express: {}: {}",
+                    section_name(change.section),
+                    change.kind
+                );
+            }
+
+            match output {
+                Some(output) => fs_err::write(output, patch.to_string())?,
+                None => print!("{}", patch),
+            }
+        }
+
+        Subcommand::Export {
+            input,
+            format,
+            hide_unbound,
+            hide_debug,
+            names,
+            output,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let map: ControlMapFile = contents.parse()?;
+
+            let options = CheatSheetOptions {
+                hide_unbound,
+                hide_debug,
+                names,
+            };
+            let exported = match format {
+                ExportFormat::Json => map.to_json(names),
+                ExportFormat::Markdown => map.cheat_sheet_markdown(options),
+                ExportFormat::Html => map.cheat_sheet_html(options),
+            };
+
+            match output {
+                Some(output) => fs_err::write(output, exported)?,
+                None => println!("{}", exported),
+            }
+        }
+
+        Subcommand::Import { input, output } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let map = ControlMapFile::from_json(&contents)
+                .with_context(|| format!("Could not import {}", input.display()))?;
+
+            fs_err::write(output, map.to_string_lossless())?;
+        }
 
-            base_map.remove_duplicates();
-            let encoded = base_map.to_string();
-            fs_err::write(output, encoded)?;
+        Subcommand::Build {
+            input,
+            output,
+            format,
+        } => {
+            let format = match format {
+                Some(format) => format,
+                None => SourceFormat::from_path(&input)?,
+            };
+
+            let contents = fs_err::read_to_string(&input)?;
+            let map = source::build(&contents, format)
+                .with_context(|| format!("Could not build {}", input.display()))?;
+
+            fs_err::write(output, map.to_string())?;
+        }
+
+        Subcommand::Render {
+            input,
+            output,
+            device,
+            context,
+            names,
+        } => {
+            let contents = fs_err::read_to_string(&input)?;
+            let map: ControlMapFile = contents.parse()?;
+
+            fs_err::write(output, map.render_svg(device, context, names))?;
         }
     }
 
     Ok(())
 }
 
+fn read_merge_input(path: &Path) -> anyhow::Result<MergeInput> {
+    let contents = fs_err::read_to_string(path)?;
+    let map = contents
+        .parse()
+        .with_context(|| format!("Could not parse {}", path.display()))?;
+
+    Ok(MergeInput {
+        label: path.display().to_string(),
+        map,
+    })
+}
+
+/// Merges the selected fields of the given files, three-way if a base is
+/// given, reporting skipped changes and conflicts along the way.
+fn merge_files(
+    inputs: &[PathBuf],
+    base: Option<&Path>,
+    on_conflict: ConflictStrategy,
+    fields: &MergeFields,
+) -> anyhow::Result<(ControlMapFile, Blame)> {
+    if inputs.is_empty() {
+        bail!("No input files specified");
+    }
+
+    let mut maps = inputs
+        .iter()
+        .map(|input| read_merge_input(input))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let base = match base {
+        Some(base) => read_merge_input(base)?,
+        None => {
+            let base = maps.remove(0);
+            return Ok(merge_overwrite(base, maps, fields));
+        }
+    };
+
+    let result = merge_three_way(&base, &maps, on_conflict, fields);
+
+    for (index, change) in &result.skipped {
+        eprintln!(
+            "Skipping change from {} that can't be merged: {}: {}",
+            maps[*index].label,
+            section_name(change.section),
+            change.kind
+        );
+    }
+
+    for conflict in &result.conflicts {
+        eprintln!(
+            "Conflict in {}: {}.{}",
+            section_name(conflict.section),
+            EventDisplay(&conflict.event, conflict.occurrence),
+            conflict.field
+        );
+
+        for (index, value) in &conflict.values {
+            eprintln!("    {} sets {}", maps[*index].label, value.describe_value());
+        }
+    }
+
+    if on_conflict == ConflictStrategy::Fail && !result.conflicts.is_empty() {
+        bail!(
+            "{} conflicting changes; pick a strategy with --on-conflict",
+            result.conflicts.len()
+        );
+    }
+
+    Ok((result.merged, result.blame))
+}
+
+fn print_blame(blame: &Blame, merged: &ControlMapFile, format: BlameFormat) -> anyhow::Result<()> {
+    match format {
+        BlameFormat::Table => print!("{}", blame.to_table(merged)),
+        BlameFormat::Json => println!("{}", serde_json::to_string_pretty(&blame.to_json(merged))?),
+    }
+
+    Ok(())
+}
+
+/// Warns about inputs in a new binding that players won't be able to choose
+/// again in the game's controls menu.
+fn warn_unremappable(event: &str, device: Device, binding: &Binding) {
+    for code in device.unremappable_codes(binding) {
+        eprintln!(
+            "warning: {} is bound to {}, which can't be remapped to in-game",
+            event,
+            device.code_name(code).unwrap_or_default()
+        );
+    }
+}
+
 fn main() {
     if let Err(err) = run() {
         eprintln!("Error: {:?}", err);
@@ -0,0 +1,1001 @@
+//! Combining several controlmap files into one.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde_json::json;
+
+use crate::columnar::ColumnPrinter;
+use crate::dedupe::DedupePolicy;
+use crate::diff::{Change, ChangeKind, EventDisplay};
+use crate::format::{ConflictSide, ControlMapFile, ControlMapLine, Device};
+use crate::input_codes::InputNames;
+use crate::input_context::{section_name, InputContext};
+use crate::patch::{FieldKind, Patch, PatchChange, PatchField};
+
+/// A controlmap file taking part in a merge, along with a label for where it
+/// came from.
+#[derive(Debug)]
+pub struct MergeInput {
+    pub label: String,
+    pub map: ControlMapFile,
+}
+
+/// Which columns of an entry a merge takes from later files. Everything else
+/// is left as it was in earlier files.
+#[derive(Debug, Clone)]
+pub struct MergeFields {
+    pub devices: Vec<Device>,
+    pub groups: Vec<FieldGroup>,
+}
+
+impl MergeFields {
+    /// Selects the given devices and groups of fields, or all of them if
+    /// either list is empty.
+    pub fn new(devices: Vec<Device>, groups: Vec<FieldGroup>) -> Self {
+        Self {
+            devices: if devices.is_empty() {
+                Device::all().to_vec()
+            } else {
+                devices
+            },
+            groups: if groups.is_empty() {
+                FieldGroup::all().to_vec()
+            } else {
+                groups
+            },
+        }
+    }
+
+    pub fn includes(&self, kind: FieldKind) -> bool {
+        match kind {
+            FieldKind::Binding(device) => {
+                self.groups.contains(&FieldGroup::Bindings) && self.devices.contains(&device)
+            }
+            FieldKind::Mappable(device) => {
+                self.groups.contains(&FieldGroup::Mappable) && self.devices.contains(&device)
+            }
+            FieldKind::EventFlag => self.groups.contains(&FieldGroup::Flags),
+        }
+    }
+}
+
+/// A group of related columns that can be selected for merging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldGroup {
+    /// What each device is bound to.
+    Bindings,
+
+    /// Whether players can remap the event on each device.
+    Mappable,
+
+    /// The event flag.
+    Flags,
+}
+
+impl FieldGroup {
+    pub fn all() -> [FieldGroup; 3] {
+        [
+            FieldGroup::Bindings,
+            FieldGroup::Mappable,
+            FieldGroup::Flags,
+        ]
+    }
+}
+
+impl FromStr for FieldGroup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bindings" => Ok(FieldGroup::Bindings),
+            "mappable" => Ok(FieldGroup::Mappable),
+            "flags" => Ok(FieldGroup::Flags),
+            _ => bail!(
+                "Unknown field group {}, expected bindings, mappable, or flags",
+                s
+            ),
+        }
+    }
+}
+
+/// Merges files by letting entries in later files overwrite entries with the
+/// same event name in the same section of earlier ones. Only the selected
+/// fields are overwritten. Events and sections that earlier files don't have
+/// are added whole, and so are extra entries for events that later files
+/// define more times than earlier ones.
+pub fn merge_overwrite(
+    base: MergeInput,
+    overrides: Vec<MergeInput>,
+    fields: &MergeFields,
+) -> (ControlMapFile, Blame) {
+    let mut blame = Blame::new(&base.label);
+    let mut merged = base.map;
+
+    for input in overrides {
+        for (section_id, section) in input.map.sections.iter().enumerate() {
+            let target = match merged.sections.get_mut(section_id) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            // When an event appears more than once, occurrences are paired
+            // up in order.
+            let mut occurrences: HashMap<&str, usize> = HashMap::new();
+
+            for entry in section.entries() {
+                let occurrence = next_occurrence(&mut occurrences, &entry.event);
+                let existing = target
+                    .entries_mut()
+                    .filter(|existing| existing.event == entry.event)
+                    .nth(occurrence);
+
+                let existing = match existing {
+                    Some(existing) => existing,
+                    None => {
+                        // A later file with more entries for an event than
+                        // earlier ones gets the extra entries added after the
+                        // last one already there. Events that aren't there
+                        // at all are added by `add_new_events` instead.
+                        let last = target.body.iter().rposition(|line| match line {
+                            ControlMapLine::Entry(existing) => existing.event == entry.event,
+                            _ => false,
+                        });
+
+                        if let Some(last) = last {
+                            for kind in FieldKind::all() {
+                                blame.record(
+                                    section_id,
+                                    &entry.event,
+                                    occurrence,
+                                    kind,
+                                    &input.label,
+                                );
+                            }
+
+                            let mut entry = entry.clone();
+                            entry.line = None;
+                            entry.raw = None;
+                            target.body.insert(last + 1, ControlMapLine::Entry(entry));
+                        }
+
+                        continue;
+                    }
+                };
+
+                // The earlier file's text is kept, so entries that end up
+                // unchanged are written back exactly as they were.
+                for kind in FieldKind::all() {
+                    let value = PatchField::read(entry, kind);
+
+                    if fields.includes(kind) && PatchField::read(existing, kind) != value {
+                        blame.record(section_id, &entry.event, occurrence, kind, &input.label);
+                        value.apply_to(existing);
+                    }
+                }
+            }
+        }
+
+        for (section_id, event, occurrence) in add_new_events(&mut merged, &input.map) {
+            for kind in FieldKind::all() {
+                blame.record(section_id, &event, occurrence, kind, &input.label);
+            }
+        }
+    }
+
+    merged.dedupe(DedupePolicy::KeepKnown);
+    (merged, blame)
+}
+
+/// Copies events from `source` that aren't in `target` into the same section
+/// of `target`, along with the comments directly above them. New events are
+/// placed after the closest event before them that both files have, so they
+/// end up near related events. Sections that `target` doesn't have at all are
+/// copied whole. Returns the section, name, and occurrence of every entry that
+/// was added.
+fn add_new_events(
+    target: &mut ControlMapFile,
+    source: &ControlMapFile,
+) -> Vec<(usize, String, usize)> {
+    let mut added = Vec::new();
+
+    for (section_id, section) in source.sections.iter().enumerate() {
+        if section_id >= target.sections.len() {
+            let mut copy = section.clone();
+            if copy.separator.is_none() {
+                copy.separator = Some(String::new());
+            }
+
+            let mut occurrences: HashMap<&str, usize> = HashMap::new();
+            for entry in section.entries() {
+                let occurrence = next_occurrence(&mut occurrences, &entry.event);
+                added.push((section_id, entry.event.clone(), occurrence));
+            }
+
+            for entry in copy.entries_mut() {
+                entry.line = None;
+            }
+
+            target.sections.push(copy);
+            continue;
+        }
+
+        let target_section = &mut target.sections[section_id];
+        // Only events the target had to begin with count as known, so every
+        // entry of a new event is added, even when it appears more than once.
+        let known: HashSet<String> = target_section
+            .entries()
+            .map(|entry| entry.event.clone())
+            .collect();
+
+        // Before any shared event has been seen, new events go above the
+        // first entry, below whatever comments head the section.
+        let mut insert_at = target_section
+            .body
+            .iter()
+            .position(|line| matches!(line, ControlMapLine::Entry(_)))
+            .unwrap_or(target_section.body.len());
+        let mut comments = Vec::new();
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+
+        for line in &section.body {
+            match line {
+                ControlMapLine::Comment { .. } => comments.push(line.clone()),
+                ControlMapLine::Conflict(_) => comments.clear(),
+                ControlMapLine::Entry(entry) if known.contains(&entry.event) => {
+                    comments.clear();
+                    insert_at = target_section
+                        .body
+                        .iter()
+                        .rposition(|line| match line {
+                            ControlMapLine::Entry(existing) => existing.event == entry.event,
+                            _ => false,
+                        })
+                        .map_or(insert_at, |position| position + 1);
+                }
+                ControlMapLine::Entry(entry) => {
+                    // Section headings and the like are already there, so
+                    // only bring along comments that are new too.
+                    for comment in comments.drain(..) {
+                        let duplicate = target_section.body.iter().any(|line| match line {
+                            ControlMapLine::Comment { text, .. } => match &comment {
+                                ControlMapLine::Comment { text: new_text, .. } => text == new_text,
+                                _ => false,
+                            },
+                            _ => false,
+                        });
+
+                        if !duplicate {
+                            target_section.body.insert(insert_at, comment);
+                            insert_at += 1;
+                        }
+                    }
+
+                    let occurrence = next_occurrence(&mut occurrences, &entry.event);
+                    added.push((section_id, entry.event.clone(), occurrence));
+
+                    // The new entry is re-rendered so it lines up with the
+                    // entries around it.
+                    let mut entry = entry.clone();
+                    entry.line = None;
+                    entry.raw = None;
+
+                    target_section
+                        .body
+                        .insert(insert_at, ControlMapLine::Entry(entry));
+                    insert_at += 1;
+                }
+            }
+        }
+    }
+
+    added
+}
+
+/// What to do when two inputs to a three-way merge change the same field in
+/// different ways.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Leave the field as it was in the base. Callers should treat any
+    /// conflicts as an error.
+    Fail,
+
+    /// Use the value from the earliest input that changed the field.
+    PreferLeft,
+
+    /// Use the value from the latest input that changed the field.
+    PreferRight,
+
+    /// Write every version of the entry between conflict markers.
+    Markers,
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(ConflictStrategy::Fail),
+            "prefer-left" => Ok(ConflictStrategy::PreferLeft),
+            "prefer-right" => Ok(ConflictStrategy::PreferRight),
+            "markers" => Ok(ConflictStrategy::Markers),
+            _ => bail!(
+                "Unknown conflict strategy {}, expected fail, prefer-left, prefer-right, or markers",
+                s
+            ),
+        }
+    }
+}
+
+/// A field that several inputs changed to different values.
+#[derive(Debug)]
+pub struct MergeConflict {
+    pub section: usize,
+    pub event: String,
+
+    /// Which entry for the event conflicts, counting from zero, when the
+    /// event appears more than once in the section.
+    pub occurrence: usize,
+
+    pub field: FieldKind,
+
+    /// The value each input changed the field to, by index into the merge's
+    /// inputs, in input order.
+    pub values: Vec<(usize, PatchField)>,
+}
+
+#[derive(Debug)]
+pub struct ThreeWayMerge {
+    pub merged: ControlMapFile,
+    pub conflicts: Vec<MergeConflict>,
+
+    /// Changes that can't be merged, like removed events, along with the
+    /// index of the input they came from.
+    pub skipped: Vec<(usize, Change)>,
+
+    pub blame: Blame,
+}
+
+/// Merges several files that were each derived from `base`. Every field of
+/// every entry is merged separately, so inputs that change different fields
+/// of the same event don't clobber each other. Changes to fields that aren't
+/// selected are ignored.
+pub fn merge_three_way(
+    base: &MergeInput,
+    inputs: &[MergeInput],
+    strategy: ConflictStrategy,
+    fields: &MergeFields,
+) -> ThreeWayMerge {
+    type FieldKey = (usize, String, usize, FieldKind);
+
+    let mut order: Vec<FieldKey> = Vec::new();
+    let mut proposals: HashMap<FieldKey, Vec<(usize, PatchField)>> = HashMap::new();
+    let mut skipped = Vec::new();
+
+    for (index, input) in inputs.iter().enumerate() {
+        let (patch, unsupported) = base.map.make_patch(&input.map);
+        skipped.extend(unsupported.into_iter().map(|change| (index, change)));
+
+        for change in patch.changes {
+            if !fields.includes(change.field.kind()) {
+                continue;
+            }
+
+            let key = (
+                change.context,
+                change.event,
+                change.occurrence,
+                change.field.kind(),
+            );
+
+            proposals
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    order.push(key);
+                    Vec::new()
+                })
+                .push((index, change.field));
+        }
+    }
+
+    let mut resolved = Patch::default();
+    let mut conflicts = Vec::new();
+    let mut blame = Blame::new(&base.label);
+
+    for key in order {
+        let values = proposals.remove(&key).unwrap();
+        let (context, event, occurrence, field) = key;
+
+        let agreed = values.iter().all(|(_, value)| value == &values[0].1);
+        let chosen = if agreed {
+            values.last()
+        } else {
+            match strategy {
+                ConflictStrategy::Fail => None,
+                ConflictStrategy::PreferLeft | ConflictStrategy::Markers => values.first(),
+                ConflictStrategy::PreferRight => values.last(),
+            }
+        };
+
+        if let Some((index, chosen)) = chosen {
+            blame.record(context, &event, occurrence, field, &inputs[*index].label);
+            resolved.changes.push(PatchChange {
+                context,
+                event: event.clone(),
+                occurrence,
+                field: chosen.clone(),
+                line: None,
+            });
+        }
+
+        if !agreed {
+            conflicts.push(MergeConflict {
+                section: context,
+                event,
+                occurrence,
+                field,
+                values,
+            });
+        }
+    }
+
+    let mut merged = base.map.clone();
+    merged.apply_patch(&resolved);
+
+    // Added events and sections don't fit in a patch, so they're copied over
+    // separately and no longer count as skipped.
+    for (index, input) in inputs.iter().enumerate() {
+        for (section_id, event, occurrence) in add_new_events(&mut merged, &input.map) {
+            for kind in FieldKind::all() {
+                blame.record(section_id, &event, occurrence, kind, &input.label);
+            }
+
+            skipped.retain(|(i, change)| match &change.kind {
+                ChangeKind::EventAdded { event: added } => {
+                    *i != index || change.section != section_id || *added != event
+                }
+                _ => true,
+            });
+        }
+
+        skipped
+            .retain(|(i, change)| *i != index || !matches!(change.kind, ChangeKind::SectionAdded));
+    }
+
+    if strategy == ConflictStrategy::Markers {
+        insert_conflict_markers(&mut merged, &conflicts, inputs);
+    }
+
+    ThreeWayMerge {
+        merged,
+        conflicts,
+        skipped,
+        blame,
+    }
+}
+
+/// Replaces every entry with conflicting fields by one version of the entry
+/// per input that changed those fields.
+fn insert_conflict_markers(
+    merged: &mut ControlMapFile,
+    conflicts: &[MergeConflict],
+    inputs: &[MergeInput],
+) {
+    // Entries are identified by section, event name, and occurrence.
+    type EntryKey<'a> = (usize, &'a str, usize);
+
+    let mut by_entry: Vec<(EntryKey, Vec<&MergeConflict>)> = Vec::new();
+    for conflict in conflicts {
+        let key = (
+            conflict.section,
+            conflict.event.as_str(),
+            conflict.occurrence,
+        );
+
+        match by_entry.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, list)) => list.push(conflict),
+            None => by_entry.push((key, vec![conflict])),
+        }
+    }
+
+    for ((section_id, event, occurrence), entry_conflicts) in by_entry {
+        let section = &mut merged.sections[section_id];
+        let position = section
+            .body
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| match line {
+                ControlMapLine::Entry(entry) => entry.event == event,
+                _ => false,
+            })
+            .nth(occurrence)
+            .map(|(position, _)| position);
+
+        let line = match position {
+            Some(position) => &mut section.body[position],
+            None => continue,
+        };
+        let entry = match line {
+            ControlMapLine::Entry(entry) => entry,
+            _ => unreachable!(),
+        };
+
+        let mut input_indices: Vec<usize> = entry_conflicts
+            .iter()
+            .flat_map(|conflict| conflict.values.iter().map(|(index, _)| *index))
+            .collect();
+        input_indices.sort_unstable();
+        input_indices.dedup();
+
+        let sides = input_indices
+            .into_iter()
+            .map(|index| {
+                let mut side = entry.clone();
+
+                for conflict in &entry_conflicts {
+                    if let Some((_, value)) = conflict.values.iter().find(|(i, _)| *i == index) {
+                        value.apply_to(&mut side);
+                    }
+                }
+
+                ConflictSide {
+                    label: inputs[index].label.clone(),
+                    entry: side,
+                }
+            })
+            .collect();
+
+        *line = ControlMapLine::Conflict(sides);
+    }
+}
+
+/// Which input last changed each field of each entry in a merge.
+#[derive(Debug)]
+pub struct Blame {
+    /// Where fields that nobody changed came from, usually the base file.
+    default_source: String,
+
+    /// Keyed by section, event name, which entry for the event counting from
+    /// zero, and field.
+    sources: HashMap<(usize, String, usize, FieldKind), String>,
+}
+
+impl Blame {
+    fn new(default_source: &str) -> Self {
+        Self {
+            default_source: default_source.to_owned(),
+            sources: HashMap::new(),
+        }
+    }
+
+    fn record(
+        &mut self,
+        section: usize,
+        event: &str,
+        occurrence: usize,
+        field: FieldKind,
+        source: &str,
+    ) {
+        self.sources.insert(
+            (section, event.to_owned(), occurrence, field),
+            source.to_owned(),
+        );
+    }
+
+    /// Tells which input the given field of an entry came from. `occurrence`
+    /// picks the entry when the event appears more than once in the section,
+    /// counting from zero.
+    pub fn source(&self, section: usize, event: &str, occurrence: usize, field: FieldKind) -> &str {
+        self.sources
+            .get(&(section, event.to_owned(), occurrence, field))
+            .unwrap_or(&self.default_source)
+    }
+
+    /// Lays out the merged file as a table, with every binding annotated with
+    /// the input it came from. Flags covers both the mappable flags and the
+    /// event flag.
+    pub fn to_table(&self, merged: &ControlMapFile) -> String {
+        let mut output = String::new();
+
+        for (section_id, section) in merged.sections.iter().enumerate() {
+            if section_id > 0 {
+                output.push('\n');
+            }
+
+            output.push_str(&format!("==== {} ====\n", section_name(section_id)));
+
+            let mut printer = ColumnPrinter::new();
+            printer.row();
+            for heading in ["Event", "Keyboard", "Mouse", "Gamepad", "Flags"] {
+                printer.add(heading);
+            }
+
+            let mut occurrences: HashMap<&str, usize> = HashMap::new();
+            for entry in section.entries() {
+                let occurrence = next_occurrence(&mut occurrences, &entry.event);
+
+                printer.row();
+                printer.add(EventDisplay(&entry.event, occurrence));
+
+                for device in Device::all() {
+                    let source = self.source(
+                        section_id,
+                        &entry.event,
+                        occurrence,
+                        FieldKind::Binding(device),
+                    );
+                    printer.add(format_args!(
+                        "{} ({})",
+                        device.format_binding(entry.binding(device), InputNames::default()),
+                        source
+                    ));
+                }
+
+                let mut flag_sources: Vec<&str> = Vec::new();
+                for kind in FieldKind::all() {
+                    if matches!(kind, FieldKind::Binding(_)) {
+                        continue;
+                    }
+
+                    let source = self.source(section_id, &entry.event, occurrence, kind);
+                    if !flag_sources.contains(&source) {
+                        flag_sources.push(source);
+                    }
+                }
+                printer.add(flag_sources.join(", "));
+            }
+
+            printer
+                .finish(&mut output)
+                .expect("writing to a String cannot fail");
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// Describes where every field of every entry in the merged file came
+    /// from as JSON.
+    pub fn to_json(&self, merged: &ControlMapFile) -> serde_json::Value {
+        let mut entries = Vec::new();
+
+        for (section_id, section) in merged.sections.iter().enumerate() {
+            let context = InputContext::from_u32(section_id as u32).map(|context| context.name());
+
+            let mut occurrences: HashMap<&str, usize> = HashMap::new();
+            for entry in section.entries() {
+                let occurrence = next_occurrence(&mut occurrences, &entry.event);
+
+                let fields: serde_json::Map<_, _> = FieldKind::all()
+                    .into_iter()
+                    .map(|kind| {
+                        let source = self.source(section_id, &entry.event, occurrence, kind);
+                        (kind.to_string(), json!(source))
+                    })
+                    .collect();
+
+                entries.push(json!({
+                    "section": section_id,
+                    "context": context,
+                    "event": entry.event,
+                    "occurrence": occurrence,
+                    "fields": fields,
+                }));
+            }
+        }
+
+        serde_json::Value::Array(entries)
+    }
+}
+
+/// Counts another entry for `event`, returning which one it is, counting from
+/// zero.
+fn next_occurrence<'a>(occurrences: &mut HashMap<&'a str, usize>, event: &'a str) -> usize {
+    let count = occurrences.entry(event).or_insert(0);
+    *count += 1;
+    *count - 1
+}
+
+/// How to print blame information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlameFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for BlameFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(BlameFormat::Table),
+            "json" => Ok(BlameFormat::Json),
+            _ => bail!("Unknown blame format {}, expected table or json", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{Binding, Input};
+    use crate::input_codes::{Gamepad, InputCode};
+    use crate::DEFAULT_CONTROLMAP;
+
+    const MAIN: usize = InputContext::MainGameplay as usize;
+    const LOCKPICKING: usize = InputContext::Lockpicking as usize;
+
+    fn vanilla() -> MergeInput {
+        MergeInput {
+            label: "vanilla".to_owned(),
+            map: DEFAULT_CONTROLMAP.parse().unwrap(),
+        }
+    }
+
+    /// A copy of the vanilla file with one entry's gamepad binding changed.
+    fn rebind(
+        label: &str,
+        section: usize,
+        event: &str,
+        occurrence: usize,
+        button: Gamepad,
+    ) -> MergeInput {
+        let mut input = vanilla();
+        input.label = label.to_owned();
+        *input
+            .map
+            .find_occurrence_mut(section, event, occurrence)
+            .unwrap()
+            .binding_mut(Device::Gamepad) = gamepad(button);
+        input
+    }
+
+    fn gamepad(button: Gamepad) -> Binding {
+        Binding {
+            inputs: vec![Input::Inputs(vec![button.to_u32()])],
+        }
+    }
+
+    fn gamepad_bindings(map: &ControlMapFile, section: usize, event: &str) -> Vec<Binding> {
+        map.sections[section]
+            .entries()
+            .filter(|entry| entry.event == event)
+            .map(|entry| entry.gamepad.clone())
+            .collect()
+    }
+
+    fn all_fields() -> MergeFields {
+        MergeFields::new(Vec::new(), Vec::new())
+    }
+
+    #[test]
+    fn combines_changes_to_different_events() {
+        let inputs = [
+            rebind("jump", MAIN, "Jump", 0, Gamepad::X),
+            rebind("sprint", MAIN, "Sprint", 0, Gamepad::B),
+        ];
+
+        let result = merge_three_way(&vanilla(), &inputs, ConflictStrategy::Fail, &all_fields());
+
+        assert!(result.conflicts.is_empty());
+        assert!(result.skipped.is_empty());
+        assert_eq!(
+            gamepad_bindings(&result.merged, MAIN, "Jump"),
+            vec![gamepad(Gamepad::X)]
+        );
+        assert_eq!(
+            gamepad_bindings(&result.merged, MAIN, "Sprint"),
+            vec![gamepad(Gamepad::B)]
+        );
+        assert_eq!(
+            result
+                .blame
+                .source(MAIN, "Sprint", 0, FieldKind::Binding(Device::Gamepad)),
+            "sprint"
+        );
+    }
+
+    #[test]
+    fn detects_conflicting_changes() {
+        let base = vanilla();
+        let inputs = [
+            rebind("left", MAIN, "Jump", 0, Gamepad::X),
+            rebind("right", MAIN, "Jump", 0, Gamepad::B),
+        ];
+
+        let failed = merge_three_way(&base, &inputs, ConflictStrategy::Fail, &all_fields());
+        assert_eq!(failed.conflicts.len(), 1);
+        assert_eq!(failed.conflicts[0].event, "Jump");
+        assert_eq!(
+            gamepad_bindings(&failed.merged, MAIN, "Jump"),
+            gamepad_bindings(&base.map, MAIN, "Jump")
+        );
+
+        let right = merge_three_way(&base, &inputs, ConflictStrategy::PreferRight, &all_fields());
+        assert_eq!(
+            gamepad_bindings(&right.merged, MAIN, "Jump"),
+            vec![gamepad(Gamepad::B)]
+        );
+    }
+
+    #[test]
+    fn agreeing_changes_do_not_conflict() {
+        let inputs = [
+            rebind("first", MAIN, "Jump", 0, Gamepad::X),
+            rebind("second", MAIN, "Jump", 0, Gamepad::X),
+        ];
+
+        let result = merge_three_way(&vanilla(), &inputs, ConflictStrategy::Fail, &all_fields());
+
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            gamepad_bindings(&result.merged, MAIN, "Jump"),
+            vec![gamepad(Gamepad::X)]
+        );
+    }
+
+    #[test]
+    fn overwrite_adds_extra_entries_for_repeated_events() {
+        let mut base = vanilla();
+        base.label = "base".to_owned();
+        let lockpicking = &mut base.map.sections[LOCKPICKING];
+        let second = lockpicking
+            .body
+            .iter()
+            .rposition(
+                |line| matches!(line, ControlMapLine::Entry(entry) if entry.event == "RotateLock"),
+            )
+            .unwrap();
+        lockpicking.body.remove(second);
+
+        let (merged, blame) = merge_overwrite(base, vec![vanilla()], &all_fields());
+
+        let events: Vec<&str> = merged.sections[LOCKPICKING]
+            .entries()
+            .map(|entry| entry.event.as_str())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "RotatePick",
+                "RotateLock",
+                "RotateLock",
+                "DebugMode",
+                "Cancel"
+            ]
+        );
+
+        let added = merged.sections[LOCKPICKING]
+            .entries()
+            .filter(|entry| entry.event == "RotateLock")
+            .nth(1)
+            .unwrap();
+        let mut vanilla = vanilla();
+        let expected = vanilla
+            .map
+            .find_occurrence_mut(LOCKPICKING, "RotateLock", 1)
+            .unwrap();
+        assert_eq!(added.keyboard, expected.keyboard);
+        assert_eq!(
+            blame.source(LOCKPICKING, "RotateLock", 0, FieldKind::EventFlag),
+            "base"
+        );
+        assert_eq!(
+            blame.source(LOCKPICKING, "RotateLock", 1, FieldKind::EventFlag),
+            "vanilla"
+        );
+    }
+
+    #[test]
+    fn overwrite_adds_every_entry_of_new_repeated_events() {
+        let mut base = vanilla();
+        base.label = "base".to_owned();
+        base.map.sections[LOCKPICKING].body.retain(
+            |line| !matches!(line, ControlMapLine::Entry(entry) if entry.event == "RotateLock"),
+        );
+
+        let (merged, _) = merge_overwrite(base, vec![vanilla()], &all_fields());
+
+        assert_eq!(
+            gamepad_bindings(&merged, LOCKPICKING, "RotateLock").len(),
+            2
+        );
+    }
+
+    #[test]
+    fn merges_repeated_events_by_occurrence() {
+        let base = vanilla();
+        let original = gamepad_bindings(&base.map, LOCKPICKING, "RotateLock");
+        let inputs = [rebind(
+            "mod",
+            LOCKPICKING,
+            "RotateLock",
+            1,
+            Gamepad::LeftStick,
+        )];
+
+        let result = merge_three_way(&base, &inputs, ConflictStrategy::Fail, &all_fields());
+
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            gamepad_bindings(&result.merged, LOCKPICKING, "RotateLock"),
+            vec![original[0].clone(), gamepad(Gamepad::LeftStick)]
+        );
+
+        let gamepad_field = FieldKind::Binding(Device::Gamepad);
+        assert_eq!(
+            result
+                .blame
+                .source(LOCKPICKING, "RotateLock", 0, gamepad_field),
+            "vanilla"
+        );
+        assert_eq!(
+            result
+                .blame
+                .source(LOCKPICKING, "RotateLock", 1, gamepad_field),
+            "mod"
+        );
+
+        let table = result.blame.to_table(&result.merged);
+        assert!(table.contains("RotateLock#2"), "{}", table);
+
+        let json = result.blame.to_json(&result.merged);
+        let blamed: Vec<_> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry["event"] == "RotateLock")
+            .map(|entry| {
+                (
+                    entry["occurrence"].clone(),
+                    entry["fields"]["gamepad"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            blamed,
+            [(json!(0), json!("vanilla")), (json!(1), json!("mod"))]
+        );
+    }
+
+    #[test]
+    fn marks_conflicts_on_the_right_occurrence() {
+        let inputs = [
+            rebind("left", LOCKPICKING, "RotateLock", 1, Gamepad::LeftStick),
+            rebind("right", LOCKPICKING, "RotateLock", 1, Gamepad::LeftTrigger),
+        ];
+
+        let result = merge_three_way(
+            &vanilla(),
+            &inputs,
+            ConflictStrategy::Markers,
+            &all_fields(),
+        );
+
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].occurrence, 1);
+
+        let body = &result.merged.sections[LOCKPICKING].body;
+        let rotate_lock: Vec<&ControlMapLine> = body
+            .iter()
+            .filter(|line| match line {
+                ControlMapLine::Entry(entry) => entry.event == "RotateLock",
+                ControlMapLine::Conflict(sides) => sides[0].entry.event == "RotateLock",
+                ControlMapLine::Comment { .. } => false,
+            })
+            .collect();
+
+        assert!(matches!(rotate_lock[0], ControlMapLine::Entry(_)));
+        match rotate_lock[1] {
+            ControlMapLine::Conflict(sides) => {
+                let sides: Vec<&Binding> = sides.iter().map(|side| &side.entry.gamepad).collect();
+                assert_eq!(
+                    sides,
+                    [&gamepad(Gamepad::LeftStick), &gamepad(Gamepad::LeftTrigger)]
+                );
+            }
+            other => panic!("expected conflict markers, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! A small patch format for changing individual fields of a controlmap.
+//!
+//! Patches name an input context in brackets, or give the number of a section
+//! the game doesn't name, then set fields of events in that context, one per
+//! line:
+//!
+//! ```text
+//! // Comments start with //, like in controlmap.txt.
+//! [Main Gameplay]
+//! Jump.gamepad = Y
+//! Ready Weapon.gamepad = LT,X
+//! Ready Weapon.gamepad_mappable = 0
+//! Sprint.flag = Movement | VATS
+//! ```
+//!
+//! When an event appears more than once in a context, like `RotateLock` in
+//! Lockpicking, later entries are picked by adding `#2`, `#3`, and so on to
+//! the event name, like `RotateLock#2.gamepad = LS`.
+//!
+//! Bindings use key names, the same way as the `bind` subcommand, and `none`
+//! clears a binding or event flag. Event flags can be written by name or in
+//! hex, like `0x801`. Values may optionally be wrapped in double quotes.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+
+use crate::diff::{Change, ChangeKind, EventDisplay};
+use crate::event_flags::EventFlags;
+use crate::format::{Binding, ControlMapEntry, ControlMapFile, Device};
+use crate::input_codes::InputNames;
+use crate::input_context::InputContext;
+
+#[derive(Debug, Default)]
+pub struct Patch {
+    pub changes: Vec<PatchChange>,
+}
+
+/// A single field of a single event to change.
+#[derive(Debug)]
+pub struct PatchChange {
+    pub context: usize,
+    pub event: String,
+
+    /// Which entry for the event to change, counting from zero, when the
+    /// event appears more than once in the context.
+    pub occurrence: usize,
+
+    pub field: PatchField,
+
+    /// The line of the patch file this change came from, if it came from one.
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchField {
+    Binding(Device, Binding),
+    Mappable(Device, bool),
+    EventFlag(Option<EventFlags>),
+}
+
+/// Which field of an entry a `PatchField` changes, without the new value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldKind {
+    Binding(Device),
+    Mappable(Device),
+    EventFlag,
+}
+
+impl Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldKind::Binding(device) => write!(f, "{}", device),
+            FieldKind::Mappable(device) => write!(f, "{}_mappable", device),
+            FieldKind::EventFlag => write!(f, "flag"),
+        }
+    }
+}
+
+impl FieldKind {
+    /// Every field of an entry apart from the event name.
+    pub fn all() -> Vec<FieldKind> {
+        let mut all = Vec::new();
+        all.extend(Device::all().map(FieldKind::Binding));
+        all.extend(Device::all().map(FieldKind::Mappable));
+        all.push(FieldKind::EventFlag);
+        all
+    }
+}
+
+impl PatchField {
+    /// Reads the current value of a field from an entry.
+    pub fn read(entry: &ControlMapEntry, kind: FieldKind) -> Self {
+        match kind {
+            FieldKind::Binding(device) => {
+                PatchField::Binding(device, entry.binding(device).clone())
+            }
+            FieldKind::Mappable(device) => PatchField::Mappable(device, entry.mappable(device)),
+            FieldKind::EventFlag => PatchField::EventFlag(entry.event_flag),
+        }
+    }
+
+    pub fn kind(&self) -> FieldKind {
+        match self {
+            PatchField::Binding(device, _) => FieldKind::Binding(*device),
+            PatchField::Mappable(device, _) => FieldKind::Mappable(*device),
+            PatchField::EventFlag(_) => FieldKind::EventFlag,
+        }
+    }
+
+    pub fn apply_to(&self, entry: &mut ControlMapEntry) {
+        match self {
+            PatchField::Binding(device, binding) => {
+                *entry.binding_mut(*device) = binding.clone();
+            }
+            PatchField::Mappable(device, mappable) => {
+                *entry.mappable_mut(*device) = *mappable;
+            }
+            PatchField::EventFlag(flag) => {
+                entry.event_flag = *flag;
+            }
+        }
+    }
+
+    /// Describes the new value of the field using key and flag names.
+    pub fn describe_value(&self) -> String {
+        match self {
+            PatchField::Binding(device, binding) => {
+                device.format_binding(binding, InputNames::default())
+            }
+            PatchField::Mappable(_, mappable) => (*mappable as u8).to_string(),
+            PatchField::EventFlag(Some(flag)) => flag.to_string(),
+            PatchField::EventFlag(None) => "none".to_owned(),
+        }
+    }
+}
+
+impl FromStr for Patch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut changes = Vec::new();
+        let mut context = None;
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header
+                    .strip_suffix(']')
+                    .with_context(|| format!("Error on line {}: missing ]", i + 1))?;
+                // Sections the game doesn't name are written by number.
+                let parsed = match name.trim().parse::<usize>() {
+                    Ok(section) => section,
+                    Err(_) => name
+                        .parse::<InputContext>()
+                        .with_context(|| format!("Error on line {}", i + 1))?
+                        as usize,
+                };
+                context = Some(parsed);
+                continue;
+            }
+
+            let mut change: PatchChange = line
+                .parse()
+                .with_context(|| format!("Error on line {}", i + 1))?;
+            change.context = context.with_context(|| {
+                format!(
+                    "Error on line {}: expected an [input context] header before any changes",
+                    i + 1
+                )
+            })?;
+            change.line = Some(i + 1);
+            changes.push(change);
+        }
+
+        Ok(Self { changes })
+    }
+}
+
+impl Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut current_context = None;
+
+        for change in &self.changes {
+            if current_context != Some(change.context) {
+                if current_context.is_some() {
+                    writeln!(f)?;
+                }
+
+                match InputContext::from_u32(change.context as u32) {
+                    Some(context) => writeln!(f, "[{}]", context.name())?,
+                    None => writeln!(f, "[{}]", change.context)?,
+                }
+
+                current_context = Some(change.context);
+            }
+
+            writeln!(f, "{}", change)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a single `Event.field = value` line, without the context.
+impl Display for PatchChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} = {}",
+            EventDisplay(&self.event, self.occurrence),
+            self.field.kind(),
+            self.field.describe_value()
+        )
+    }
+}
+
+/// Parses a single `Event.field = value` line. The context is filled in by
+/// the caller.
+impl FromStr for PatchChange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .context("Expected a line like Event.field = value")?;
+        let (event, field) = key
+            .trim()
+            .rsplit_once('.')
+            .context("Expected a field name after the event name, like Jump.gamepad")?;
+
+        // Later entries of a repeated event are written like `RotateLock#2`.
+        let (event, occurrence) = match event.trim().rsplit_once('#') {
+            Some((name, number)) => {
+                let number: usize = number
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&number| number > 0)
+                    .with_context(|| format!("Invalid entry number {} for {}", number, name))?;
+                (name, number - 1)
+            }
+            None => (event, 0),
+        };
+
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+
+        let field = match field.trim() {
+            "keyboard" | "mouse" | "gamepad" => {
+                let device: Device = field.trim().parse()?;
+                PatchField::Binding(device, device.parse_binding(value, InputNames::default())?)
+            }
+            "keyboard_mappable" | "mouse_mappable" | "gamepad_mappable" => {
+                let device: Device = field.trim().trim_end_matches("_mappable").parse()?;
+                let mappable = match value {
+                    "0" | "false" => false,
+                    "1" | "true" => true,
+                    _ => bail!("Invalid mappable flag {}, expected 0 or 1", value),
+                };
+                PatchField::Mappable(device, mappable)
+            }
+            "flag" => {
+                if value.eq_ignore_ascii_case("none") {
+                    PatchField::EventFlag(None)
+                } else {
+                    PatchField::EventFlag(Some(value.parse()?))
+                }
+            }
+            other => bail!(
+                "Unknown field {}, expected keyboard, mouse, gamepad, \
+                 keyboard_mappable, mouse_mappable, gamepad_mappable, or flag",
+                other
+            ),
+        };
+
+        Ok(Self {
+            context: 0,
+            event: event.trim().to_owned(),
+            occurrence,
+            field,
+            line: None,
+        })
+    }
+}
+
+impl ControlMapFile {
+    /// Builds the smallest patch that turns this file into `modified`.
+    /// Changes that patches can't express, like added or removed events, are
+    /// returned separately.
+    pub fn make_patch(&self, modified: &ControlMapFile) -> (Patch, Vec<Change>) {
+        let mut patch = Patch::default();
+        let mut unsupported = Vec::new();
+
+        for change in self.diff(modified) {
+            let (event, occurrence, field) = match change.kind {
+                ChangeKind::BindingChanged {
+                    event,
+                    occurrence,
+                    device,
+                    new,
+                    ..
+                } => (event, occurrence, PatchField::Binding(device, new)),
+                ChangeKind::MappableChanged {
+                    event,
+                    occurrence,
+                    device,
+                    new,
+                    ..
+                } => (event, occurrence, PatchField::Mappable(device, new)),
+                ChangeKind::EventFlagChanged {
+                    event,
+                    occurrence,
+                    new,
+                    ..
+                } => (event, occurrence, PatchField::EventFlag(new)),
+                _ => {
+                    unsupported.push(change);
+                    continue;
+                }
+            };
+
+            patch.changes.push(PatchChange {
+                context: change.section,
+                event,
+                occurrence,
+                field,
+                line: None,
+            });
+        }
+
+        (patch, unsupported)
+    }
+
+    /// Applies every change in a patch to this file. Changes to events that
+    /// don't exist are skipped and returned.
+    pub fn apply_patch<'a>(&mut self, patch: &'a Patch) -> Vec<&'a PatchChange> {
+        let mut missing = Vec::new();
+
+        for change in &patch.changes {
+            let entry =
+                match self.find_occurrence_mut(change.context, &change.event, change.occurrence) {
+                    Some(entry) => entry,
+                    None => {
+                        missing.push(change);
+                        continue;
+                    }
+                };
+
+            change.field.apply_to(entry);
+        }
+
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Input;
+    use crate::input_codes::{Gamepad, InputCode};
+    use crate::input_context::InputContext;
+    use crate::DEFAULT_CONTROLMAP;
+
+    fn lockpicking_rotate_lock(map: &ControlMapFile) -> Vec<Binding> {
+        map.sections[InputContext::Lockpicking as usize]
+            .entries()
+            .filter(|entry| entry.event == "RotateLock")
+            .map(|entry| entry.gamepad.clone())
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_changes_to_repeated_events() {
+        let base: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+        let mut modified = base.clone();
+        let left_stick = Binding {
+            inputs: vec![Input::Inputs(vec![Gamepad::LeftStick.to_u32()])],
+        };
+        *modified
+            .find_occurrence_mut(InputContext::Lockpicking as usize, "RotateLock", 1)
+            .unwrap()
+            .binding_mut(Device::Gamepad) = left_stick.clone();
+
+        let (patch, unsupported) = base.make_patch(&modified);
+        assert!(unsupported.is_empty());
+
+        let written = patch.to_string();
+        assert!(
+            written.contains("RotateLock#2.gamepad = LeftStick"),
+            "{}",
+            written
+        );
+
+        let parsed: Patch = written.parse().unwrap();
+        let mut patched = base.clone();
+        assert!(patched.apply_patch(&parsed).is_empty());
+
+        let original = lockpicking_rotate_lock(&base);
+        assert_eq!(
+            lockpicking_rotate_lock(&patched),
+            vec![original[0].clone(), left_stick]
+        );
+        assert!(patched.diff(&modified).is_empty());
+    }
+}
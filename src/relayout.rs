@@ -0,0 +1,272 @@
+//! Moving keyboard bindings from one keyboard layout to another, so a file
+//! written for one layout keeps its labels on another.
+
+use std::collections::HashSet;
+
+use crate::format::{ControlMapFile, Device, Input};
+use crate::input_codes::{InputCode, Keyboard, KeyboardLayout};
+
+/// A key that an entry's keyboard binding used, and what happened to it.
+#[derive(Debug)]
+pub struct KeyMove {
+    pub section: usize,
+    pub event: String,
+    pub from: Keyboard,
+    pub outcome: KeyMoveOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyMoveOutcome {
+    /// The binding now uses the key with the same label in the new layout.
+    Moved(Keyboard),
+
+    /// The new layout doesn't have a key with the same label, so the binding
+    /// was left alone.
+    Missing,
+
+    /// The key with the same label in the new layout stays where it is or is
+    /// already used in the same section, so the binding was left alone
+    /// rather than bind two events to one key.
+    Taken(Keyboard),
+}
+
+impl ControlMapFile {
+    /// Rewrites every keyboard binding written for the `from` layout so that
+    /// it uses the keys with the same labels in the `to` layout. Keys in
+    /// `positional`, like the WASD movement keys, stay where they physically
+    /// are instead. Keys are never moved onto a positional key or one the
+    /// section already uses. Aliases are left alone, since they don't name
+    /// keys. Returns every key that was moved or had to be left alone.
+    pub fn relayout(
+        &mut self,
+        from: KeyboardLayout,
+        to: KeyboardLayout,
+        positional: &[Keyboard],
+    ) -> Vec<KeyMove> {
+        let used: Vec<HashSet<u32>> = self
+            .sections
+            .iter()
+            .map(|section| {
+                section
+                    .entries()
+                    .flat_map(|entry| {
+                        self.resolve_binding(entry.binding(Device::Keyboard), Device::Keyboard)
+                    })
+                    .flatten()
+                    .collect()
+            })
+            .collect();
+
+        let mut moves = Vec::new();
+
+        for (section_id, section) in self.sections.iter_mut().enumerate() {
+            for entry in section.entries_mut() {
+                let event = entry.event.clone();
+
+                for input in &mut entry.binding_mut(Device::Keyboard).inputs {
+                    let codes = match input {
+                        Input::Inputs(codes) => codes,
+                        Input::Alias { .. } => continue,
+                    };
+
+                    for code in codes {
+                        let key = match Keyboard::from_u32(*code) {
+                            Some(key) => key,
+                            None => continue,
+                        };
+
+                        if positional.contains(&key) {
+                            continue;
+                        }
+
+                        let outcome = match relabeled_key(key, from, to) {
+                            Some(moved) if moved == key => continue,
+                            Some(moved)
+                                if positional.contains(&moved)
+                                    || used[section_id].contains(&moved.to_u32()) =>
+                            {
+                                KeyMoveOutcome::Taken(moved)
+                            }
+                            Some(moved) => {
+                                *code = moved.to_u32();
+                                KeyMoveOutcome::Moved(moved)
+                            }
+                            None => KeyMoveOutcome::Missing,
+                        };
+
+                        moves.push(KeyMove {
+                            section: section_id,
+                            event: event.clone(),
+                            from: key,
+                            outcome,
+                        });
+                    }
+                }
+            }
+        }
+
+        moves
+    }
+}
+
+/// The key labeled in the `to` layout the way `key` is labeled in the `from`
+/// layout. Labels shared by more than one key don't say which one is meant,
+/// so they count as missing.
+fn relabeled_key(key: Keyboard, from: KeyboardLayout, to: KeyboardLayout) -> Option<Keyboard> {
+    let label = from.name(key);
+    let mut matches = Keyboard::all()
+        .iter()
+        .copied()
+        .filter(|&other| to.name(other).eq_ignore_ascii_case(label));
+
+    match (matches.next(), matches.next()) {
+        (Some(other), None) => Some(other),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_context::InputContext;
+    use crate::DEFAULT_CONTROLMAP;
+
+    fn keyboard_codes(map: &ControlMapFile, event: &str) -> Vec<Input> {
+        map.sections[InputContext::MainGameplay as usize]
+            .entries()
+            .find(|entry| entry.event == event)
+            .unwrap()
+            .keyboard
+            .inputs
+            .clone()
+    }
+
+    #[test]
+    fn never_moves_onto_taken_keys() {
+        let mut map: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+        let conflicts = map.conflicts().len();
+        let positional = [Keyboard::W, Keyboard::A, Keyboard::S, Keyboard::D];
+
+        let moves = map.relayout(KeyboardLayout::Qwerty, KeyboardLayout::Azerty, &positional);
+
+        // Z and Q are where W and A are on QWERTY, which stay put for
+        // movement.
+        let shout = moves.iter().find(|key_move| key_move.event == "Shout");
+        assert_eq!(
+            shout.map(|key_move| key_move.outcome),
+            Some(KeyMoveOutcome::Taken(Keyboard::W))
+        );
+        assert_eq!(
+            keyboard_codes(&map, "Shout"),
+            [Input::Inputs(vec![Keyboard::Z.to_u32()])]
+        );
+        assert_eq!(
+            keyboard_codes(&map, "Forward"),
+            [Input::Inputs(vec![Keyboard::W.to_u32()])]
+        );
+
+        // M is where the semicolon is on QWERTY.
+        assert_eq!(
+            keyboard_codes(&map, "Quick Map"),
+            [Input::Inputs(vec![Keyboard::Semicolon.to_u32()])]
+        );
+
+        assert_eq!(map.conflicts().len(), conflicts);
+    }
+
+    #[test]
+    fn moves_to_qwertz() {
+        let mut map: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+        let positional = [Keyboard::W, Keyboard::A, Keyboard::S, Keyboard::D];
+
+        let moves = map.relayout(KeyboardLayout::Qwerty, KeyboardLayout::Qwertz, &positional);
+        let outcome = |event: &str| {
+            moves
+                .iter()
+                .find(|key_move| key_move.section == 0 && key_move.event == event)
+                .map(|key_move| key_move.outcome)
+        };
+
+        // Z and Y swap places.
+        assert_eq!(outcome("Shout"), Some(KeyMoveOutcome::Moved(Keyboard::Y)));
+        assert_eq!(
+            keyboard_codes(&map, "Shout"),
+            [Input::Inputs(vec![Keyboard::Y.to_u32()])]
+        );
+
+        // The backtick key is labeled with a circumflex on QWERTZ, and no key
+        // is labeled with a backtick.
+        assert_eq!(outcome("Console"), Some(KeyMoveOutcome::Missing));
+        assert_eq!(
+            keyboard_codes(&map, "Console"),
+            [
+                Input::Inputs(vec![Keyboard::Backtick.to_u32()]),
+                Input::Inputs(vec![Keyboard::Kanji.to_u32()])
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_to_dvorak() {
+        let mut map: ControlMapFile = DEFAULT_CONTROLMAP.parse().unwrap();
+        let positional = [Keyboard::W, Keyboard::A, Keyboard::S, Keyboard::D];
+
+        let moves = map.relayout(KeyboardLayout::Qwerty, KeyboardLayout::Dvorak, &positional);
+        let outcome = |event: &str| {
+            moves
+                .iter()
+                .find(|key_move| key_move.section == 0 && key_move.event == event)
+                .map(|key_move| key_move.outcome)
+        };
+
+        assert_eq!(
+            outcome("Quick Stats"),
+            Some(KeyMoveOutcome::Moved(Keyboard::LeftBracket))
+        );
+        assert_eq!(
+            outcome("Favorites"),
+            Some(KeyMoveOutcome::Moved(Keyboard::X))
+        );
+
+        // Dvorak's Z is where QWERTY's slash is, which Quick Stats uses.
+        assert_eq!(
+            outcome("Shout"),
+            Some(KeyMoveOutcome::Taken(Keyboard::ForwardSlash))
+        );
+
+        // Dvorak's E is where QWERTY's D is, which stays put for movement.
+        assert_eq!(
+            outcome("Activate"),
+            Some(KeyMoveOutcome::Taken(Keyboard::D))
+        );
+        assert_eq!(
+            keyboard_codes(&map, "Activate"),
+            [Input::Inputs(vec![Keyboard::E.to_u32()])]
+        );
+
+        // The backtick key keeps its label.
+        assert_eq!(outcome("Console"), None);
+    }
+
+    #[test]
+    fn labels_name_one_key_in_each_layout() {
+        let layouts = [
+            KeyboardLayout::Qwerty,
+            KeyboardLayout::Azerty,
+            KeyboardLayout::Qwertz,
+            KeyboardLayout::Dvorak,
+        ];
+
+        for layout in layouts {
+            let mut labels = HashSet::new();
+            for &key in Keyboard::all() {
+                assert!(
+                    labels.insert(layout.name(key).to_ascii_lowercase()),
+                    "{:?} labels more than one key {}",
+                    layout,
+                    layout.name(key)
+                );
+            }
+        }
+    }
+}
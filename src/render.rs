@@ -0,0 +1,461 @@
+//! Drawing diagrams of a device with every button labeled by the events it's
+//! bound to, as SVG.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::cheatsheet::escape_html;
+use crate::format::{ControlMapFile, Device};
+use crate::input_codes::{
+    Gamepad, InputCode, InputNames, Keyboard, KeyboardLayout, Mouse, PadStyle,
+};
+use crate::input_context::InputContext;
+
+const WIDTH: f32 = 1200.0;
+const CALLOUT_HEIGHT: f32 = 560.0;
+const KEY_SIZE: f32 = 52.0;
+
+/// A button on a controller or mouse diagram, labeled with a line out to the
+/// side of the drawing.
+struct Button {
+    code: u32,
+    x: f32,
+    y: f32,
+    shape: Shape,
+    label: &'static str,
+    side: Side,
+}
+
+enum Shape {
+    Circle(f32),
+    Rect(f32, f32),
+}
+
+#[derive(PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// A key on the keyboard diagram, or a gap between keys if `key` is `None`.
+/// Widths and heights are in key units.
+struct KeySlot {
+    key: Option<Keyboard>,
+    label: &'static str,
+    width: f32,
+    height: f32,
+}
+
+const fn key(key: Keyboard, label: &'static str, width: f32) -> KeySlot {
+    KeySlot {
+        key: Some(key),
+        label,
+        width,
+        height: 1.0,
+    }
+}
+
+const fn tall_key(key: Keyboard, label: &'static str) -> KeySlot {
+    KeySlot {
+        key: Some(key),
+        label,
+        width: 1.0,
+        height: 2.0,
+    }
+}
+
+const fn gap(width: f32) -> KeySlot {
+    KeySlot {
+        key: None,
+        label: "",
+        width,
+        height: 1.0,
+    }
+}
+
+/// The keyboard diagram, row by row, laid out like a full-size US keyboard.
+/// Gaps only separate groups of keys.
+#[rustfmt::skip]
+const KEYBOARD_ROWS: &[&[KeySlot]] = &[
+    &[
+        key(Keyboard::Escape, "Esc", 1.0), gap(1.0),
+        key(Keyboard::F1, "F1", 1.0), key(Keyboard::F2, "F2", 1.0),
+        key(Keyboard::F3, "F3", 1.0), key(Keyboard::F4, "F4", 1.0), gap(0.5),
+        key(Keyboard::F5, "F5", 1.0), key(Keyboard::F6, "F6", 1.0),
+        key(Keyboard::F7, "F7", 1.0), key(Keyboard::F8, "F8", 1.0), gap(0.5),
+        key(Keyboard::F9, "F9", 1.0), key(Keyboard::F10, "F10", 1.0),
+        key(Keyboard::F11, "F11", 1.0), key(Keyboard::F12, "F12", 1.0), gap(0.25),
+        key(Keyboard::PrintScreen, "PrtSc", 1.0), key(Keyboard::ScrollLock, "ScrLk", 1.0),
+        key(Keyboard::Pause, "Pause", 1.0),
+    ],
+    &[
+        key(Keyboard::Backtick, "`", 1.0), key(Keyboard::One, "1", 1.0),
+        key(Keyboard::Two, "2", 1.0), key(Keyboard::Three, "3", 1.0),
+        key(Keyboard::Four, "4", 1.0), key(Keyboard::Five, "5", 1.0),
+        key(Keyboard::Six, "6", 1.0), key(Keyboard::Seven, "7", 1.0),
+        key(Keyboard::Eight, "8", 1.0), key(Keyboard::Nine, "9", 1.0),
+        key(Keyboard::Zero, "0", 1.0), key(Keyboard::Minus, "-", 1.0),
+        key(Keyboard::Equals, "=", 1.0), key(Keyboard::Backspace, "Backspace", 2.0), gap(0.25),
+        key(Keyboard::Insert, "Ins", 1.0), key(Keyboard::Home, "Home", 1.0),
+        key(Keyboard::PageUp, "PgUp", 1.0), gap(0.25),
+        key(Keyboard::NumLock, "Num", 1.0), key(Keyboard::NumSlash, "/", 1.0),
+        key(Keyboard::NumpadStar, "*", 1.0), key(Keyboard::NumMinus, "-", 1.0),
+    ],
+    &[
+        key(Keyboard::Tab, "Tab", 1.5), key(Keyboard::Q, "Q", 1.0),
+        key(Keyboard::W, "W", 1.0), key(Keyboard::E, "E", 1.0),
+        key(Keyboard::R, "R", 1.0), key(Keyboard::T, "T", 1.0),
+        key(Keyboard::Y, "Y", 1.0), key(Keyboard::U, "U", 1.0),
+        key(Keyboard::I, "I", 1.0), key(Keyboard::O, "O", 1.0),
+        key(Keyboard::P, "P", 1.0), key(Keyboard::LeftBracket, "[", 1.0),
+        key(Keyboard::RightBracket, "]", 1.0), key(Keyboard::BackSlash, "\\", 1.5), gap(0.25),
+        key(Keyboard::Delete, "Del", 1.0), key(Keyboard::End, "End", 1.0),
+        key(Keyboard::PageDown, "PgDn", 1.0), gap(0.25),
+        key(Keyboard::Num7, "7", 1.0), key(Keyboard::Num8, "8", 1.0),
+        key(Keyboard::Num9, "9", 1.0), tall_key(Keyboard::NumPlus, "+"),
+    ],
+    &[
+        key(Keyboard::CapsLock, "Caps", 1.75), key(Keyboard::A, "A", 1.0),
+        key(Keyboard::S, "S", 1.0), key(Keyboard::D, "D", 1.0),
+        key(Keyboard::F, "F", 1.0), key(Keyboard::G, "G", 1.0),
+        key(Keyboard::H, "H", 1.0), key(Keyboard::J, "J", 1.0),
+        key(Keyboard::K, "K", 1.0), key(Keyboard::L, "L", 1.0),
+        key(Keyboard::Semicolon, ";", 1.0), key(Keyboard::Apostrophe, "'", 1.0),
+        key(Keyboard::Enter, "Enter", 2.25), gap(3.5),
+        key(Keyboard::Num4, "4", 1.0), key(Keyboard::Num5, "5", 1.0),
+        key(Keyboard::Num6, "6", 1.0),
+    ],
+    &[
+        key(Keyboard::LeftShift, "Shift", 2.25), key(Keyboard::Z, "Z", 1.0),
+        key(Keyboard::X, "X", 1.0), key(Keyboard::C, "C", 1.0),
+        key(Keyboard::V, "V", 1.0), key(Keyboard::B, "B", 1.0),
+        key(Keyboard::N, "N", 1.0), key(Keyboard::M, "M", 1.0),
+        key(Keyboard::Comma, ",", 1.0), key(Keyboard::Period, ".", 1.0),
+        key(Keyboard::ForwardSlash, "/", 1.0), key(Keyboard::RightShift, "Shift", 2.75), gap(1.25),
+        key(Keyboard::UpArrow, "Up", 1.0), gap(1.25),
+        key(Keyboard::Num1, "1", 1.0), key(Keyboard::Num2, "2", 1.0),
+        key(Keyboard::Num3, "3", 1.0), tall_key(Keyboard::NumEnter, "Enter"),
+    ],
+    &[
+        key(Keyboard::LeftControl, "Ctrl", 1.25), key(Keyboard::LeftWindows, "Win", 1.25),
+        key(Keyboard::LeftAlt, "Alt", 1.25), key(Keyboard::Spacebar, "Space", 6.25),
+        key(Keyboard::RightAlt, "Alt", 1.25), key(Keyboard::RightWindows, "Win", 1.25),
+        key(Keyboard::Apps, "Menu", 1.25), key(Keyboard::RightControl, "Ctrl", 1.25), gap(0.25),
+        key(Keyboard::LeftArrow, "Left", 1.0), key(Keyboard::DownArrow, "Down", 1.0),
+        key(Keyboard::RightArrow, "Right", 1.0), gap(0.25),
+        key(Keyboard::Num0, "0", 2.0), key(Keyboard::NumPeriod, ".", 1.0),
+    ],
+];
+
+#[rustfmt::skip]
+fn gamepad_buttons(style: PadStyle) -> Vec<Button> {
+    use Shape::{Circle, Rect};
+    use Side::{Left, Right};
+
+    // Buttons the style renames get its names instead of the short Xbox ones.
+    let button = |code: Gamepad, x, y, shape, label, side| Button {
+        code: code.to_u32(),
+        x,
+        y,
+        shape,
+        label: if style.name(code) == code.name() { label } else { style.name(code) },
+        side,
+    };
+
+    vec![
+        button(Gamepad::LeftTrigger, 470.0, 110.0, Rect(60.0, 24.0), "LT", Left),
+        button(Gamepad::LeftShoulder, 470.0, 145.0, Rect(80.0, 18.0), "LB", Left),
+        button(Gamepad::Back, 560.0, 185.0, Circle(10.0), "Back", Left),
+        button(Gamepad::LeftStick, 480.0, 240.0, Circle(30.0), "LS", Left),
+        button(Gamepad::LeftThumb, 480.0, 240.0, Circle(10.0), "L3", Left),
+        button(Gamepad::DpadUp, 540.0, 298.0, Rect(20.0, 22.0), "Up", Left),
+        button(Gamepad::DpadLeft, 518.0, 320.0, Rect(22.0, 20.0), "Left", Left),
+        button(Gamepad::DpadDown, 540.0, 342.0, Rect(20.0, 22.0), "Down", Left),
+        button(Gamepad::DpadRight, 562.0, 320.0, Rect(22.0, 20.0), "Right", Left),
+        button(Gamepad::RightTrigger, 730.0, 110.0, Rect(60.0, 24.0), "RT", Right),
+        button(Gamepad::RightShoulder, 730.0, 145.0, Rect(80.0, 18.0), "RB", Right),
+        button(Gamepad::Start, 640.0, 185.0, Circle(10.0), "Start", Right),
+        button(Gamepad::Y, 740.0, 225.0, Circle(15.0), "Y", Right),
+        button(Gamepad::X, 710.0, 255.0, Circle(15.0), "X", Right),
+        button(Gamepad::B, 770.0, 255.0, Circle(15.0), "B", Right),
+        button(Gamepad::A, 740.0, 285.0, Circle(15.0), "A", Right),
+        button(Gamepad::RightStick, 660.0, 320.0, Circle(30.0), "RS", Right),
+        button(Gamepad::RightThumb, 660.0, 320.0, Circle(10.0), "R3", Right),
+    ]
+}
+
+const GAMEPAD_BODY: &str = "\
+<path d=\"M 470 170 Q 600 150 730 170 Q 820 185 840 300 Q 860 430 790 450 \
+Q 740 460 700 390 L 500 390 Q 460 460 410 450 Q 340 430 360 300 Q 380 185 470 170 Z\" \
+fill=\"#ddd\" stroke=\"#333\" stroke-width=\"2\"/>\n";
+
+#[rustfmt::skip]
+fn mouse_buttons() -> Vec<Button> {
+    use Shape::{Circle, Rect};
+    use Side::{Left, Right};
+
+    let button = |code: Mouse, x, y, shape, label, side| Button {
+        code: code.to_u32(),
+        x,
+        y,
+        shape,
+        label,
+        side,
+    };
+
+    vec![
+        button(Mouse::WheelUp, 600.0, 175.0, Rect(14.0, 16.0), "Wheel Up", Left),
+        button(Mouse::Button1, 560.0, 200.0, Rect(70.0, 110.0), "LMB", Left),
+        button(Mouse::Button4, 535.0, 275.0, Rect(20.0, 18.0), "Mouse4", Left),
+        button(Mouse::Button5, 535.0, 297.0, Rect(20.0, 18.0), "Mouse5", Left),
+        button(Mouse::Button6, 535.0, 319.0, Rect(20.0, 18.0), "Mouse6", Left),
+        button(Mouse::Button7, 535.0, 341.0, Rect(20.0, 18.0), "Mouse7", Left),
+        button(Mouse::Button8, 535.0, 363.0, Rect(20.0, 18.0), "Mouse8", Left),
+        button(Mouse::Button2, 640.0, 200.0, Rect(70.0, 110.0), "RMB", Right),
+        button(Mouse::Button3, 600.0, 200.0, Rect(14.0, 16.0), "MMB", Right),
+        button(Mouse::WheelDown, 600.0, 225.0, Rect(14.0, 16.0), "Wheel Down", Right),
+        button(Mouse::Move, 600.0, 340.0, Circle(16.0), "Move", Right),
+    ]
+}
+
+const MOUSE_BODY: &str = "\
+<ellipse cx=\"600\" cy=\"280\" rx=\"90\" ry=\"150\" fill=\"#ddd\" stroke=\"#333\" stroke-width=\"2\"/>\n";
+
+impl ControlMapFile {
+    /// Draws a diagram of a device, with every button labeled by the events
+    /// it's bound to in the given input context. Aliases are followed, so
+    /// events that share another event's inputs are labeled too. Keys and
+    /// buttons are labeled with `names`.
+    pub fn render_svg(&self, device: Device, context: InputContext, names: InputNames) -> String {
+        let events = self.events_by_code(device, context, names);
+
+        let (height, body) = match device {
+            Device::Keyboard => render_keyboard(&events, names.layout),
+            Device::Gamepad => (
+                CALLOUT_HEIGHT,
+                render_callouts(GAMEPAD_BODY, &gamepad_buttons(names.pad_style), &events),
+            ),
+            Device::Mouse => (
+                CALLOUT_HEIGHT,
+                render_callouts(MOUSE_BODY, &mouse_buttons(), &events),
+            ),
+        };
+
+        let mut output = String::new();
+        writeln!(
+            output,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+             font-family=\"sans-serif\">",
+            w = WIDTH,
+            h = height
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n\
+             <text x=\"20\" y=\"34\" font-size=\"22\" font-weight=\"bold\">{} ({})</text>",
+            context.name(),
+            device
+        )
+        .unwrap();
+        output.push_str(&body);
+        output.push_str("</svg>\n");
+        output
+    }
+
+    /// Lists the events bound to each input code on a device in one context.
+    fn events_by_code(
+        &self,
+        device: Device,
+        context: InputContext,
+        names: InputNames,
+    ) -> HashMap<u32, Vec<String>> {
+        let mut events: HashMap<u32, Vec<String>> = HashMap::new();
+
+        let section = match self.sections.get(context as usize) {
+            Some(section) => section,
+            None => return events,
+        };
+
+        for entry in section.entries() {
+            for chord in self.resolve_binding(entry.binding(device), device) {
+                for (i, &code) in chord.iter().enumerate() {
+                    // Chords are labeled on every key, naming the other keys.
+                    let label = if chord.len() > 1 {
+                        let others: Vec<&str> = chord
+                            .iter()
+                            .enumerate()
+                            .filter(|&(j, _)| j != i)
+                            .map(|(_, &other)| device.named_code(other, names).unwrap_or("?"))
+                            .collect();
+                        format!("{} (with {})", entry.event, others.join("+"))
+                    } else {
+                        entry.event.clone()
+                    };
+
+                    let list = events.entry(code).or_default();
+                    if !list.contains(&label) {
+                        list.push(label);
+                    }
+                }
+            }
+        }
+
+        events
+    }
+}
+
+/// Draws a device with its buttons labeled out to the sides, in the order
+/// they're listed, so that leader lines don't cross.
+fn render_callouts(body: &str, buttons: &[Button], events: &HashMap<u32, Vec<String>>) -> String {
+    let mut output = String::from(body);
+
+    for side in [Side::Left, Side::Right] {
+        let column: Vec<&Button> = buttons.iter().filter(|b| b.side == side).collect();
+
+        let (line_x, text_x, anchor) = match side {
+            Side::Left => (380.0, 370.0, "end"),
+            Side::Right => (820.0, 830.0, "start"),
+        };
+        let spacing = (CALLOUT_HEIGHT - 100.0) / column.len() as f32;
+
+        for (i, button) in column.into_iter().enumerate() {
+            let label_y = 80.0 + spacing * (i as f32 + 0.5);
+            let (text, color) = match events.get(&button.code) {
+                Some(events) => (events.join(", "), "#000"),
+                None => ("unbound".to_owned(), "#999"),
+            };
+
+            writeln!(
+                output,
+                "<polyline points=\"{},{} {},{} {},{}\" fill=\"none\" stroke=\"#888\"/>\n\
+                 <text x=\"{}\" y=\"{}\" font-size=\"13\" text-anchor=\"{}\" fill=\"{}\">\
+                 <tspan font-weight=\"bold\">{}</tspan>: {}</text>",
+                button.x,
+                button.y,
+                line_x,
+                label_y,
+                text_x,
+                label_y,
+                text_x,
+                label_y + 4.0,
+                anchor,
+                color,
+                button.label,
+                escape_html(&text)
+            )
+            .unwrap();
+        }
+    }
+
+    // Buttons go on top, so leader lines seem to start at their edges.
+    for button in buttons {
+        let fill = if events.contains_key(&button.code) {
+            "#8cf"
+        } else {
+            "#fff"
+        };
+
+        match button.shape {
+            Shape::Circle(radius) => writeln!(
+                output,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"#333\"/>",
+                button.x, button.y, radius, fill
+            ),
+            Shape::Rect(width, height) => writeln!(
+                output,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" fill=\"{}\" stroke=\"#333\"/>",
+                button.x - width / 2.0,
+                button.y - height / 2.0,
+                width,
+                height,
+                fill
+            ),
+        }
+        .unwrap();
+    }
+
+    output
+}
+
+/// Draws the keyboard, returning the height of the drawing along with it.
+fn render_keyboard(events: &HashMap<u32, Vec<String>>, layout: KeyboardLayout) -> (f32, String) {
+    let mut output = String::new();
+    let left = 20.0;
+    let top = 60.0;
+
+    for (row_index, row) in KEYBOARD_ROWS.iter().enumerate() {
+        // The function row is set apart from the rest.
+        let row_offset = if row_index > 0 { KEY_SIZE / 2.0 } else { 0.0 };
+        let y = top + row_index as f32 * KEY_SIZE + row_offset;
+        let mut x = left;
+
+        for slot in row.iter() {
+            let width = slot.width * KEY_SIZE;
+
+            if let Some(key) = slot.key {
+                let height = slot.height * KEY_SIZE;
+                let bound = events.get(&key.to_u32());
+                let fill = if bound.is_some() { "#8cf" } else { "#f4f4f4" };
+
+                writeln!(
+                    output,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" fill=\"{}\" stroke=\"#333\"/>\n\
+                     <text x=\"{}\" y=\"{}\" font-size=\"10\" font-weight=\"bold\">{}</text>",
+                    x + 2.0,
+                    y + 2.0,
+                    width - 4.0,
+                    height - 4.0,
+                    fill,
+                    x + 6.0,
+                    y + 14.0,
+                    escape_html(key_label(key, slot.label, layout))
+                )
+                .unwrap();
+
+                for (i, event) in bound.into_iter().flatten().take(3).enumerate() {
+                    let max_chars = (slot.width * 9.0) as usize;
+                    let text = if i == 2 && bound.unwrap().len() > 3 {
+                        "…".to_owned()
+                    } else if event.chars().count() > max_chars {
+                        let short: String = event.chars().take(max_chars - 1).collect();
+                        format!("{}…", short)
+                    } else {
+                        event.clone()
+                    };
+
+                    writeln!(
+                        output,
+                        "<text x=\"{}\" y=\"{}\" font-size=\"8\"><title>{}</title>{}</text>",
+                        x + 5.0,
+                        y + 26.0 + i as f32 * 9.0,
+                        escape_html(event),
+                        escape_html(&text)
+                    )
+                    .unwrap();
+                }
+            }
+
+            x += width;
+        }
+    }
+
+    let height = top + KEYBOARD_ROWS.len() as f32 * KEY_SIZE + KEY_SIZE;
+    (height, output)
+}
+
+/// The label to draw on a key. Keys the layout renames get its symbol for
+/// them if it has one, or its name otherwise.
+fn key_label(key: Keyboard, qwerty_label: &'static str, layout: KeyboardLayout) -> &'static str {
+    let name = layout.name(key);
+    if name == key.name() {
+        return qwerty_label;
+    }
+
+    layout
+        .aliases(key)
+        .iter()
+        .find(|alias| alias.chars().count() == 1)
+        .copied()
+        .unwrap_or(name)
+}
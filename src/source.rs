@@ -0,0 +1,283 @@
+//! A format for writing controlmaps by hand in TOML or YAML, using key names
+//! instead of input codes.
+//!
+//! Every input context is a list of events, in the order they should appear:
+//!
+//! ```toml
+//! [[MainGameplay]]
+//! event = "Forward"
+//! keyboard = "W"
+//! gamepad = "none"
+//! mappable = ["keyboard", "mouse"]
+//! flag = ["Movement", "VATS"]
+//!
+//! [[MainGameplay]]
+//! event = "Activate"
+//! comment = "Also picks up items"
+//! keyboard = ["E", "Enter"]
+//! gamepad = "A"
+//! mappable = true
+//! ```
+//!
+//! Bindings are written the same way as for the `bind` subcommand, either as
+//! one string or as a list of alternatives. Devices that aren't listed are
+//! left unbound. Event flags can be a number, a string like `"0x801"` or
+//! `"Movement | VATS"`, or a list of flag names. Every input context the game
+//! expects has to be present, and contexts are always written out in the
+//! order the game expects.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::event_flags::EventFlags;
+use crate::format::{
+    Binding, ControlMapEntry, ControlMapFile, ControlMapLine, ControlMapSection, Device,
+};
+use crate::input_codes::InputNames;
+use crate::input_context::InputContext;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Toml,
+    Yaml,
+}
+
+impl SourceFormat {
+    /// Guesses the format of a file from its extension.
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+
+        extension.parse().with_context(|| {
+            format!(
+                "Can't tell the format of {} from its extension, pass --format",
+                path.display()
+            )
+        })
+    }
+}
+
+impl FromStr for SourceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "toml" => Ok(SourceFormat::Toml),
+            "yaml" | "yml" => Ok(SourceFormat::Yaml),
+            _ => bail!("Unknown source format {}, expected toml or yaml", s),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventSource {
+    event: String,
+
+    /// Written as a comment on the line above the event.
+    #[serde(default)]
+    comment: Option<String>,
+
+    #[serde(default)]
+    keyboard: BindingSource,
+
+    #[serde(default)]
+    mouse: BindingSource,
+
+    #[serde(default)]
+    gamepad: BindingSource,
+
+    #[serde(default)]
+    mappable: MappableSource,
+
+    #[serde(default)]
+    flag: Option<FlagSource>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BindingSource {
+    One(String),
+    Alternatives(Vec<String>),
+}
+
+impl Default for BindingSource {
+    fn default() -> Self {
+        BindingSource::Alternatives(Vec::new())
+    }
+}
+
+impl BindingSource {
+    fn parse(&self, device: Device) -> anyhow::Result<Binding> {
+        let alternatives = match self {
+            BindingSource::One(binding) => std::slice::from_ref(binding),
+            BindingSource::Alternatives(alternatives) => alternatives.as_slice(),
+        };
+
+        let mut binding = Binding::default();
+        for alternative in alternatives {
+            binding.inputs.extend(
+                device
+                    .parse_binding(alternative, InputNames::default())?
+                    .inputs,
+            );
+        }
+
+        Ok(binding)
+    }
+}
+
+/// Which devices an event can be remapped on: either true or false for every
+/// device, or a list of devices.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MappableSource {
+    All(bool),
+    Devices(Vec<Device>),
+}
+
+impl Default for MappableSource {
+    fn default() -> Self {
+        MappableSource::All(false)
+    }
+}
+
+impl MappableSource {
+    fn includes(&self, device: Device) -> bool {
+        match self {
+            MappableSource::All(all) => *all,
+            MappableSource::Devices(devices) => devices.contains(&device),
+        }
+    }
+}
+
+/// User event flags, either as a number, as text that `EventFlags` can
+/// parse, or as a list of flag names.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FlagSource {
+    Number(u32),
+    Text(String),
+    Names(Vec<String>),
+}
+
+impl FlagSource {
+    fn parse(&self) -> anyhow::Result<EventFlags> {
+        match self {
+            FlagSource::Number(flag) => Ok(EventFlags::from_bits(*flag)),
+            FlagSource::Text(flag) => flag.parse(),
+            FlagSource::Names(names) => {
+                let mut flags = EventFlags::default();
+                for name in names {
+                    flags |= name.parse()?;
+                }
+                Ok(flags)
+            }
+        }
+    }
+}
+
+/// Reads a controlmap written in the given source format.
+pub fn build(source: &str, format: SourceFormat) -> anyhow::Result<ControlMapFile> {
+    let contexts: BTreeMap<String, Vec<EventSource>> = match format {
+        SourceFormat::Toml => toml::from_str(source)?,
+        SourceFormat::Yaml => serde_yaml::from_str(source)?,
+    };
+
+    let mut sections: Vec<Option<ControlMapSection>> = vec![None; InputContext::all().len()];
+
+    for (name, events) in contexts {
+        let context: InputContext = name.parse()?;
+
+        let mut body = Vec::new();
+        for event in events {
+            if let Some(comment) = &event.comment {
+                body.push(ControlMapLine::Comment {
+                    text: comment.clone(),
+                    raw: None,
+                });
+            }
+
+            let entry = build_entry(&event)
+                .with_context(|| format!("Error in {}: {}", context.name(), event.event))?;
+            body.push(ControlMapLine::Entry(entry));
+        }
+
+        if body.is_empty() {
+            bail!("{} has no events", context.name());
+        }
+
+        let section = &mut sections[context as usize];
+        if section.is_some() {
+            bail!("{} is listed more than once", context.name());
+        }
+
+        *section = Some(ControlMapSection {
+            body,
+            separator: if context as usize == 0 {
+                None
+            } else {
+                Some(String::new())
+            },
+        });
+    }
+
+    let missing: Vec<&str> = InputContext::all()
+        .iter()
+        .filter(|context| sections[**context as usize].is_none())
+        .map(|context| context.name())
+        .collect();
+    if !missing.is_empty() {
+        bail!("Missing input contexts: {}", missing.join(", "));
+    }
+
+    Ok(ControlMapFile {
+        sections: sections.into_iter().flatten().collect(),
+        line_ending: "\n",
+        trailing_newline: true,
+    })
+}
+
+fn build_entry(event: &EventSource) -> anyhow::Result<ControlMapEntry> {
+    Ok(ControlMapEntry {
+        event: event.event.clone(),
+        keyboard: event.keyboard.parse(Device::Keyboard)?,
+        mouse: event.mouse.parse(Device::Mouse)?,
+        gamepad: event.gamepad.parse(Device::Gamepad)?,
+        keyboard_mappable: event.mappable.includes(Device::Keyboard),
+        mouse_mappable: event.mappable.includes(Device::Mouse),
+        gamepad_mappable: event.mappable.includes(Device::Gamepad),
+        event_flag: event.flag.as_ref().map(FlagSource::parse).transpose()?,
+        line: None,
+        raw: None,
+        original: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_every_context() {
+        let source = r#"
+            [[MainGameplay]]
+            event = "Forward"
+            keyboard = "W"
+            mappable = ["keyboard", "mouse"]
+            flag = ["Movement", "VATS"]
+        "#;
+
+        let err = build(source, SourceFormat::Toml).unwrap_err();
+
+        assert!(err
+            .to_string()
+            .starts_with("Missing input contexts: Menu, Console, ItemMenu,"));
+    }
+}